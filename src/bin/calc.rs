@@ -0,0 +1,417 @@
+extern crate calc_rs;
+extern crate dirs;
+extern crate rustyline;
+
+use calc_rs::engine::{evaluate, evaluate_each, CalcError, Object, Span, State};
+use calc_rs::storage;
+use rustyline::{error::ReadlineError, Config, Editor};
+use std::{env,
+          fs::{self, File},
+          io::{self, BufRead, BufReader, Write},
+          path::{Path, PathBuf},
+          process,
+          time::{SystemTime, UNIX_EPOCH}};
+
+const PROMPT: &str = "calc_rs > ";
+
+const USAGE: &str = "\
+usage: calc [--each] [--strict] [SCRIPT]
+
+With no SCRIPT, starts the interactive calculator. With one, evaluates each
+line of it in turn and prints the results. Use `-` to read the script from
+standard input.
+
+options:
+    --each      show the result of every statement on a line, not just the last
+    --strict    stop with a non-zero exit code at the first error in a script
+    --help      show this message";
+
+fn main() {
+    let mut each = false;
+    let mut strict = false;
+    let mut script = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--each" => each = true,
+            "--strict" => strict = true,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return;
+            }
+            option if option.starts_with("--") => usage_error(&format!("unknown option {}", option)),
+            _ if script.is_some() => usage_error("only one script can be run at a time"),
+            _ => script = Some(arg),
+        }
+    }
+
+    match script {
+        Some(path) => process::exit(run_script(&path, each, strict)),
+        None => repl(each),
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+/// Evaluates the script at `path`, or standard input if it is `-`, against a
+/// fresh `State`, printing each result. Scripts don't touch the saved state,
+/// so they give the same answers wherever they run.
+///
+/// Returns the exit code: 0 if the script ran, 1 if `strict` is set and a
+/// line failed, and 2 if the script couldn't be read or the results couldn't
+/// be written.
+fn run_script(path: &str, each: bool, strict: bool) -> i32 {
+    let (name, reader): (&str, Box<dyn BufRead>) = if path == "-" {
+        ("<stdin>", Box::new(BufReader::new(io::stdin())))
+    } else {
+        match File::open(path) {
+            Ok(file) => (path, Box::new(BufReader::new(file))),
+            Err(error) => {
+                eprintln!("error: could not open {}: {}", path, error);
+                return 2;
+            }
+        }
+    };
+
+    let stdout = io::stdout();
+    let stderr = io::stderr();
+    match run(name, reader, &mut stdout.lock(), &mut stderr.lock(), each, strict) {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("error: could not write the results: {}", error);
+            2
+        }
+    }
+}
+
+/// Evaluates `reader` one line at a time, writing results to `out` and
+/// errors to `err`, where `name` says which script they came from. A line
+/// that opens a `/* */` comment goes on until the comment is closed. Returns
+/// the exit code like `run_script`, or the error if writing failed.
+fn run<R, O, E>(
+    name: &str,
+    reader: R,
+    out: &mut O,
+    err: &mut E,
+    each: bool,
+    strict: bool,
+) -> io::Result<i32>
+where
+    R: BufRead,
+    O: Write,
+    E: Write,
+{
+    let mut state = State::new();
+    let mut lines = reader.lines().enumerate();
+    loop {
+        // Gather the next entry, and the number of its first line.
+        let mut entry = String::new();
+        let mut first = 0;
+        for (index, line) in &mut lines {
+            let line = match line {
+                Ok(line) => line,
+                Err(error) => {
+                    writeln!(err, "error: could not read {}: {}", name, error)?;
+                    return Ok(2);
+                }
+            };
+            if entry.is_empty() {
+                if line.chars().all(char::is_whitespace) {
+                    continue;
+                }
+                first = index + 1;
+            } else {
+                entry.push('\n');
+            }
+            entry.push_str(&line);
+            if !comment_open(&entry) {
+                break;
+            }
+        }
+        // A comment that is never closed is still evaluated, to show the error.
+        if entry.is_empty() {
+            return Ok(0);
+        }
+
+        for result in run_line(&mut state, &entry, each) {
+            match result {
+                Object::Nil => {}
+                Object::Error(error) => {
+                    report(err, name, first, &entry, error)?;
+                    if strict {
+                        return Ok(1);
+                    }
+                }
+                result => writeln!(out, "{}", result)?,
+            }
+        }
+    }
+}
+
+/// Whether `text` ends inside a `/* */` comment.
+fn comment_open(text: &str) -> bool {
+    let mut rest = text;
+    loop {
+        let line_comment = rest.find('#');
+        let block_comment = rest.find("/*");
+        match (line_comment, block_comment) {
+            (Some(start), block) if block.is_none_or(|block| start < block) => {
+                match rest[start..].find('\n') {
+                    Some(end) => rest = &rest[start + end..],
+                    None => return false,
+                }
+            }
+            (_, Some(start)) => match rest[start + 2..].find("*/") {
+                Some(end) => rest = &rest[start + 2 + end + 2..],
+                None => return true,
+            },
+            _ => return false,
+        }
+    }
+}
+
+/// Writes out an error from the entry starting on line `first` of a script.
+/// Since there is no prompt above it to point the carets at, the line the
+/// error is on is shown too.
+fn report<E: Write>(
+    err: &mut E,
+    name: &str,
+    first: usize,
+    entry: &str,
+    error: CalcError,
+) -> io::Result<()> {
+    let start = error.span().map_or(0, |span| span.start);
+    let line_start = entry[..start].rfind('\n').map_or(0, |newline| newline + 1);
+    let line = entry[line_start..].lines().next().unwrap_or("");
+    let number = first + entry[..line_start].matches('\n').count();
+
+    writeln!(err, "{}:{}: error: {}", name, number, error)?;
+    writeln!(err, "    {}", line)?;
+    if let Some(span) = error.span() {
+        let end = span.end.min(line_start + line.len());
+        let error = error.at(Span::new(start - line_start, end - line_start));
+        if let Some(underline) = error.underline(line) {
+            writeln!(err, "    {}", underline)?;
+        }
+    }
+    Ok(())
+}
+
+fn repl(each: bool) {
+    let mut state = get_storage().unwrap_or_default();
+
+    // Up/down and Ctrl-R come from rustyline, so hand it the saved history
+    // and keep it in step with `State` as new entries come in.
+    let config = Config::builder()
+        .history_ignore_dups(true)
+        .auto_add_history(false)
+        .build();
+    let mut editor = Editor::<()>::with_config(config);
+    for entry in &state.history {
+        editor.add_history_entry(entry.as_str());
+    }
+
+    show("about()", evaluate(&mut state, "about()"));
+
+    loop {
+        match editor.readline(PROMPT) {
+            Ok(entry) => {
+                if entry.chars().all(char::is_whitespace) {
+                    continue;
+                }
+
+                editor.add_history_entry(entry.as_str());
+                state.add_entry(&entry);
+                for result in run_line(&mut state, &entry, each) {
+                    show(&entry, result);
+                }
+                set_storage(&state);
+            }
+            // Ctrl-C clears the line, like in most shells.
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(error) => {
+                eprintln!("error: {}", error);
+                break;
+            }
+        }
+    }
+}
+
+/// Evaluates a line, which can hold several statements, like `a = 2; b = 3;
+/// a * b`. Normally only the last result is kept, but `each` keeps them all.
+fn run_line(state: &mut State, line: &str, each: bool) -> Vec<Object> {
+    let results = if each {
+        evaluate_each(state, line)
+    } else {
+        vec![evaluate(state, line)]
+    };
+    state.set_ans(results.last().unwrap());
+    results
+}
+
+fn show(input: &str, output: Object) {
+    match output {
+        Object::Nil => {}
+        Object::Error(error) => {
+            // Line up carets under the part of the input that went wrong.
+            if let Some(underline) = error.underline(input) {
+                eprintln!("{}{}", " ".repeat(PROMPT.chars().count()), underline);
+            }
+            eprintln!("error: {}", error);
+        }
+        _ => println!("{}", output),
+    }
+}
+
+/// The file `State` is saved to, inside the user's data directory (for
+/// example `~/.local/share/calc_rs/state.json` on Linux).
+fn storage_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("calc_rs").join("state.json"))
+}
+
+fn set_storage(state: &State) {
+    if let Some(path) = storage_path() {
+        if let Err(error) = save_state(&path, state) {
+            eprintln!("error: could not save to {}: {}", path.display(), error);
+        }
+    }
+}
+
+fn get_storage() -> Option<State> {
+    load_state(&storage_path()?)
+}
+
+/// Writes `state` to the file at `path`, making its directory if needed.
+fn save_state(path: &Path, state: &State) -> io::Result<()> {
+    path.parent().map_or(Ok(()), fs::create_dir_all)?;
+    fs::write(path, storage::save(state))
+}
+
+/// Reads the state saved in the file at `path`, or `None` if there isn't
+/// one. Whatever can be read is kept, and if that isn't everything the old
+/// file is copied next to it, like `state.backup-1700000000.json`.
+fn load_state(path: &Path) -> Option<State> {
+    let string = fs::read_to_string(path).ok()?;
+    let loaded = storage::load(&string);
+
+    for problem in &loaded.problems {
+        eprintln!("warning: {}", problem);
+    }
+    // Keep the old file next to the new one, since it is about to be
+    // overwritten with only the parts that could be read.
+    if let Some(backup) = loaded.backup {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let backup_path = path.with_file_name(format!("state.backup-{}.json", seconds));
+        match fs::write(&backup_path, backup) {
+            Ok(()) => eprintln!("the old state was kept in {}", backup_path.display()),
+            Err(error) => eprintln!(
+                "error: could not back up the old state to {}: {}",
+                backup_path.display(),
+                error
+            ),
+        }
+    }
+
+    Some(loaded.state)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Runs `script`, returning the exit code and what was written to the
+    /// output and to the errors.
+    fn script(script: &str, each: bool, strict: bool) -> (i32, String, String) {
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let code = run("test.calc", script.as_bytes(), &mut out, &mut err, each, strict).unwrap();
+        (code, String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap())
+    }
+
+    /// A directory of its own for a test to write files into.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("calc_rs-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn scripts() {
+        let (code, out, err) = script("a = 2\n\n  \nb = a * 3 # six\nb + 1\n", false, false);
+        assert_eq!((code, out.as_str(), err.as_str()), (0, "7\n", ""));
+
+        // Only the last result on a line is shown, unless asked for them all.
+        let (_, out, _) = script("2 + 3; ans * 2\n", false, false);
+        assert_eq!(out, "10\n");
+        let (_, out, _) = script("2 + 3; ans * 2\nans + 1\n", true, false);
+        assert_eq!(out, "5\n10\n11\n");
+    }
+
+    #[test]
+    fn script_errors() {
+        let failing = "1 + 1\n2 + nope\n3 + 3\n";
+        let (code, out, err) = script(failing, false, false);
+        assert_eq!((code, out.as_str()), (0, "2\n6\n"));
+        assert_eq!(err, "test.calc:2: error: no variable named nope\n    2 + nope\n        ^^^^\n");
+
+        let (code, out, _) = script(failing, false, true);
+        assert_eq!((code, out.as_str()), (1, "2\n"));
+
+        let mut out = Vec::new();
+        let code = run("test.calc", &b"1\n\xff\n"[..], &mut out, &mut Vec::new(), false, false);
+        assert_eq!((code.unwrap(), out), (2, b"1\n".to_vec()));
+
+        assert_eq!(run_script("/nonexistent/test.calc", false, false), 2);
+    }
+
+    #[test]
+    fn block_comments() {
+        let commented = "/* Rent for the year,\n   from the lease */\nrent = 1200 * 12 # dollars\n\
+                         rent /* plus the\n deposit */ + 1200\n";
+        assert_eq!(script(commented, false, true), (0, "15600\n".to_string(), String::new()));
+
+        // Errors give the line they are on, not the line the entry started on.
+        let (code, _, err) = script("1 /* one\n two */ + nope\n", false, true);
+        assert_eq!(code, 1);
+        let expected = "test.calc:2: error: no variable named nope\n     two */ + nope\n              ^^^^\n";
+        assert_eq!(err, expected);
+
+        let (code, out, err) = script("1\n/* never closed\n2\n", false, true);
+        assert_eq!((code, out.as_str()), (1, "1\n"));
+        assert!(err.starts_with("test.calc:2: error: "), "{}", err);
+    }
+
+    #[test]
+    fn state_file() {
+        if let Some(path) = storage_path() {
+            assert!(path.ends_with("calc_rs/state.json"));
+        }
+
+        let dir = scratch("state_file");
+        let path = dir.join("calc_rs").join("state.json");
+        assert!(load_state(&path).is_none());
+
+        let mut state = State::new();
+        evaluate(&mut state, "a = 6 * 7");
+        save_state(&path, &state).unwrap();
+        let mut loaded = load_state(&path).unwrap();
+        assert_eq!(evaluate(&mut loaded, "a"), Object::Integer(42));
+
+        // A file that can't be read is kept before it is overwritten.
+        fs::write(&path, "{not json").unwrap();
+        assert!(load_state(&path).is_some());
+        let backups: Vec<_> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.to_string_lossy().contains("state.backup-"))
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), "{not json");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
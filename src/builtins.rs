@@ -0,0 +1,421 @@
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive};
+use std::cmp::Ordering;
+
+use model::{CalcError, Object, Quantity};
+
+/// How many arguments a built-in function accepts.
+#[derive(Clone, Copy, Debug)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+    Between(usize, usize),
+}
+
+impl Arity {
+    fn accepts(self, count: usize) -> bool {
+        match self {
+            Arity::Exactly(exactly) => count == exactly,
+            Arity::AtLeast(least) => count >= least,
+            Arity::Between(least, most) => count >= least && count <= most,
+        }
+    }
+}
+
+/// A function that is always available to call from an expression, like
+/// `sqrt(2)`.
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: Arity,
+    function: fn(Vec<Object>) -> Object,
+}
+
+impl Builtin {
+    /// Calls the function with already evaluated arguments, checking that
+    /// there are the right number of them first.
+    pub fn call(&self, args: Vec<Object>) -> Object {
+        if !self.arity.accepts(args.len()) {
+            return wrong_arity(self.name, self.arity, args.len());
+        }
+
+        (self.function)(args)
+    }
+}
+
+/// The error for calling a function with the wrong number of arguments.
+pub fn wrong_arity(name: &str, arity: Arity, given: usize) -> Object {
+    // "argument" agrees with the last number, like in "1 to 2 arguments".
+    let (expected, count) = match arity {
+        Arity::Exactly(count) => (count.to_string(), count),
+        Arity::AtLeast(count) => (format!("at least {}", count), count),
+        Arity::Between(least, most) => (format!("{} to {}", least, most), most),
+    };
+    Object::Error(CalcError::type_mismatch(format!(
+        "{} takes {} argument{} but {} {} given",
+        name,
+        expected,
+        if count == 1 { "" } else { "s" },
+        given,
+        if given == 1 { "was" } else { "were" }
+    )))
+}
+
+const BUILTINS: &[Builtin] = &[
+    Builtin { name: "sqrt", arity: Arity::Exactly(1), function: sqrt },
+    Builtin { name: "abs", arity: Arity::Exactly(1), function: abs },
+    Builtin { name: "sin", arity: Arity::Exactly(1), function: sin },
+    Builtin { name: "cos", arity: Arity::Exactly(1), function: cos },
+    Builtin { name: "tan", arity: Arity::Exactly(1), function: tan },
+    Builtin { name: "asin", arity: Arity::Exactly(1), function: asin },
+    Builtin { name: "acos", arity: Arity::Exactly(1), function: acos },
+    Builtin { name: "atan", arity: Arity::Exactly(1), function: atan },
+    Builtin { name: "ln", arity: Arity::Exactly(1), function: ln },
+    Builtin { name: "log10", arity: Arity::Exactly(1), function: log10 },
+    Builtin { name: "log", arity: Arity::Exactly(2), function: log },
+    Builtin { name: "exp", arity: Arity::Exactly(1), function: exp },
+    Builtin { name: "floor", arity: Arity::Exactly(1), function: floor },
+    Builtin { name: "ceil", arity: Arity::Exactly(1), function: ceil },
+    Builtin { name: "round", arity: Arity::Exactly(1), function: round },
+    Builtin { name: "min", arity: Arity::AtLeast(1), function: min },
+    Builtin { name: "max", arity: Arity::AtLeast(1), function: max },
+    Builtin { name: "gcd", arity: Arity::AtLeast(2), function: gcd },
+    Builtin { name: "lcm", arity: Arity::AtLeast(2), function: lcm },
+    Builtin { name: "popcount", arity: Arity::Exactly(1), function: popcount },
+    Builtin { name: "bit", arity: Arity::Exactly(2), function: bit },
+    Builtin { name: "sum", arity: Arity::AtLeast(1), function: sum },
+    Builtin { name: "product", arity: Arity::AtLeast(1), function: product },
+    Builtin { name: "mean", arity: Arity::AtLeast(1), function: mean },
+    Builtin { name: "len", arity: Arity::Exactly(1), function: len },
+    Builtin { name: "transpose", arity: Arity::Exactly(1), function: transpose },
+    Builtin { name: "det", arity: Arity::Exactly(1), function: det },
+    Builtin { name: "inverse", arity: Arity::Exactly(1), function: inverse },
+    Builtin { name: "rank", arity: Arity::Exactly(1), function: rank },
+    Builtin { name: "linsolve", arity: Arity::Exactly(2), function: linsolve },
+];
+
+/// Looks up a built-in function by name.
+pub fn find(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+// Functions that only make sense on floats. Real arguments outside of where
+// a function is defined give a domain error rather than `NaN`, while complex
+// arguments are always allowed.
+macro_rules! float_functions {
+    ( $( $name:ident($arg:ident) $( if $domain:expr )* ),* ) => {
+        $(
+            fn $name(args: Vec<Object>) -> Object {
+                match args[0] {
+                    Object::Complex(complex) => complex.$name().into(),
+                    ref real => match real.as_float() {
+                        Some($arg) if true $( && $domain )* => $arg.$name().into(),
+                        Some(_) => out_of_domain(stringify!($name)),
+                        None => not_a_number(stringify!($name), &args),
+                    },
+                }
+            }
+        )*
+    };
+}
+
+float_functions!(
+    sin(x),
+    cos(x),
+    tan(x),
+    asin(x) if x.abs() <= 1.0,
+    acos(x) if x.abs() <= 1.0,
+    atan(x),
+    ln(x) if x > 0.0,
+    log10(x) if x > 0.0,
+    exp(x)
+);
+
+/// The error for calling `name` with `args` it can't use, which is either
+/// because they aren't numbers at all or because one is complex and the
+/// function only works on real numbers.
+fn not_a_number(name: &str, args: &[Object]) -> Object {
+    let complex = args.iter().any(|arg| matches!(*arg, Object::Complex(_)));
+    Object::Error(CalcError::type_mismatch(format!(
+        "{} can only be applied to {}numbers",
+        name,
+        if complex { "real " } else { "" }
+    )))
+}
+
+fn out_of_domain(name: &str) -> Object {
+    Object::Error(CalcError::domain(format!(
+        "{} isn't defined for that number",
+        name
+    )))
+}
+
+fn sqrt(args: Vec<Object>) -> Object {
+    if let Object::Quantity(ref quantity) = args[0] {
+        let half = BigRational::new(1.into(), 2.into());
+        return quantity.clone().pow(Object::Rational(half));
+    }
+
+    // Perfect squares, like `sqrt(16)` or `sqrt(4/9)`, stay exact.
+    if let Some(ratio) = args[0].as_rational() {
+        if !ratio.is_negative() {
+            let numer = ratio.numer().sqrt();
+            let denom = ratio.denom().sqrt();
+            if &numer * &numer == *ratio.numer() && &denom * &denom == *ratio.denom() {
+                return BigRational::new(numer, denom).into();
+            }
+        }
+    }
+
+    // Negative numbers have imaginary roots, so `sqrt(-4)` is `2i`.
+    match args[0].as_complex() {
+        Some(complex) => complex.sqrt().into(),
+        None => not_a_number("sqrt", &args),
+    }
+}
+
+fn abs(args: Vec<Object>) -> Object {
+    match args[0] {
+        Object::Integer(int) => match int.checked_abs() {
+            Some(int) => Object::Integer(int),
+            None => BigInt::from(int).abs().into(),
+        },
+        Object::BigInteger(ref int) | Object::InBase(ref int, _) => int.abs().into(),
+        Object::Rational(ref ratio) => ratio.abs().into(),
+        Object::Float(float) => Object::Float(float.abs()),
+        Object::Complex(complex) => complex.norm().into(),
+        Object::Quantity(ref quantity) => Quantity {
+            value: Box::new(abs(vec![*quantity.value.clone()])),
+            ..quantity.clone()
+        }.into(),
+        _ => not_a_number("abs", &args),
+    }
+}
+
+fn log(args: Vec<Object>) -> Object {
+    match (args[0].as_float(), args[1].as_float()) {
+        (Some(float), Some(base)) if float > 0.0 && base > 0.0 && base != 1.0 => {
+            float.log(base).into()
+        }
+        (Some(_), Some(_)) => out_of_domain("log"),
+        _ => not_a_number("log", &args),
+    }
+}
+
+// Rounding keeps integers and rationals exact, and floats stay floats.
+macro_rules! rounding_functions {
+    ( $( $name:ident ),* ) => {
+        $(
+            fn $name(args: Vec<Object>) -> Object {
+                match args[0] {
+                    Object::Float(float) => Object::Float(float.$name()),
+                    ref exact => match exact.as_rational() {
+                        Some(ratio) => ratio.$name().into(),
+                        None => not_a_number(stringify!($name), &args),
+                    },
+                }
+            }
+        )*
+    };
+}
+
+rounding_functions!(floor, ceil, round);
+
+/// The values that a function of any number of arguments, like `sum`, works
+/// on. These are the arguments themselves, or the elements of a single list,
+/// so that `sum(1, 2, 3)` and `sum([1, 2, 3])` are the same.
+fn values(args: Vec<Object>) -> Vec<Object> {
+    match args.as_slice() {
+        [Object::List(_)] => match args.into_iter().next() {
+            Some(Object::List(items)) => items,
+            _ => unreachable!(),
+        },
+        _ => args,
+    }
+}
+
+fn empty_list(name: &str) -> Object {
+    Object::Error(CalcError::domain(format!(
+        "{} isn't defined for an empty list",
+        name
+    )))
+}
+
+/// Finds the argument that comes first in the given ordering.
+fn extreme(name: &str, args: Vec<Object>, wanted: Ordering) -> Object {
+    let mut args = values(args).into_iter();
+    let mut best = match args.next() {
+        Some(best) => best,
+        None => return empty_list(name),
+    };
+
+    for arg in args {
+        match arg.compare(&best) {
+            Some(ordering) if ordering == wanted => best = arg,
+            Some(_) => {}
+            None => return not_a_number(name, &[arg, best]),
+        }
+    }
+
+    // Catches a single argument that can't be ordered.
+    if best.compare(&best).is_none() {
+        return not_a_number(name, &[best]);
+    }
+    best
+}
+
+fn min(args: Vec<Object>) -> Object {
+    extreme("min", args, Ordering::Less)
+}
+
+fn max(args: Vec<Object>) -> Object {
+    extreme("max", args, Ordering::Greater)
+}
+
+/// Folds numbers together with `function`, starting from `empty` if there
+/// aren't any.
+fn fold_numbers(
+    name: &str,
+    args: Vec<Object>,
+    empty: Object,
+    function: fn(Object, Object) -> Object,
+) -> Object {
+    let mut result: Option<Object> = None;
+
+    for arg in values(args) {
+        if Quantity::from_object(arg.clone()).is_none() {
+            return not_a_number(name, &[arg]);
+        }
+        result = Some(match result {
+            Some(result) => match function(result, arg) {
+                error @ Object::Error(_) => return error,
+                result => result,
+            },
+            None => arg,
+        });
+    }
+
+    result.unwrap_or(empty)
+}
+
+fn sum(args: Vec<Object>) -> Object {
+    fold_numbers("sum", args, Object::Integer(0), |lhs, rhs| lhs + rhs)
+}
+
+fn product(args: Vec<Object>) -> Object {
+    fold_numbers("product", args, Object::Integer(1), |lhs, rhs| lhs * rhs)
+}
+
+fn mean(args: Vec<Object>) -> Object {
+    let values = values(args);
+    if values.is_empty() {
+        return empty_list("mean");
+    }
+    let count = Object::Integer(values.len() as i64);
+    match fold_numbers("mean", values, Object::Integer(0), |lhs, rhs| lhs + rhs) {
+        error @ Object::Error(_) => error,
+        total => total / count,
+    }
+}
+
+/// The number of elements in a list, or of rows in a matrix.
+fn len(args: Vec<Object>) -> Object {
+    match args[0] {
+        Object::List(ref items) => Object::Integer(items.len() as i64),
+        Object::Matrix(ref matrix) => Object::Integer(matrix.rows() as i64),
+        _ => Object::Error(CalcError::type_mismatch("len can only be applied to lists")),
+    }
+}
+
+fn not_a_matrix(name: &str) -> Object {
+    Object::Error(CalcError::type_mismatch(format!(
+        "{} can only be applied to matrices",
+        name
+    )))
+}
+
+// Linear algebra on a single matrix.
+macro_rules! matrix_functions {
+    ( $( $name:ident => $method:ident ),* ) => {
+        $(
+            fn $name(args: Vec<Object>) -> Object {
+                match args.into_iter().next().unwrap() {
+                    Object::Matrix(matrix) => matrix.$method().into(),
+                    _ => not_a_matrix(stringify!($name)),
+                }
+            }
+        )*
+    };
+}
+
+matrix_functions!(transpose => transpose, det => determinant, inverse => inverse, rank => rank);
+
+/// Solves `A x = b` for `x`, given `A` and `b`.
+fn linsolve(args: Vec<Object>) -> Object {
+    match args[0] {
+        Object::Matrix(ref matrix) => match args[1] {
+            Object::List(ref rhs) => matrix.solve(rhs),
+            _ => Object::Error(CalcError::type_mismatch(
+                "linsolve solves against a list, like `linsolve(A, [1, 2])`",
+            )),
+        },
+        _ => not_a_matrix("linsolve"),
+    }
+}
+
+/// Folds integer arguments together with `function`.
+fn fold_integers(name: &str, args: Vec<Object>, function: fn(&BigInt, &BigInt) -> BigInt) -> Object {
+    let mut result: Option<BigInt> = None;
+
+    for arg in args {
+        let int = match arg.as_big_integer() {
+            Some(int) => int,
+            None => return not_an_integer(name),
+        };
+        result = Some(match result {
+            Some(result) => function(&result, &int),
+            None => int,
+        });
+    }
+
+    result.unwrap().into()
+}
+
+fn not_an_integer(name: &str) -> Object {
+    Object::Error(CalcError::type_mismatch(format!(
+        "{} can only be applied to integers",
+        name
+    )))
+}
+
+fn gcd(args: Vec<Object>) -> Object {
+    fold_integers("gcd", args, BigInt::gcd)
+}
+
+fn lcm(args: Vec<Object>) -> Object {
+    fold_integers("lcm", args, BigInt::lcm)
+}
+
+/// The number of bits that are set in a non-negative integer.
+fn popcount(args: Vec<Object>) -> Object {
+    match args[0].as_big_integer() {
+        Some(ref int) if int.is_negative() => out_of_domain("popcount"),
+        Some(int) => Object::Integer(int.magnitude().count_ones() as i64),
+        None => not_an_integer("popcount"),
+    }
+}
+
+/// Whether bit `n` of an integer is set, as `1` or `0`, counting from the
+/// least significant bit. Negative integers act like two's complement.
+fn bit(args: Vec<Object>) -> Object {
+    let (int, index) = match (args[0].as_big_integer(), args[1].as_big_integer()) {
+        (Some(int), Some(index)) => (int, index),
+        _ => return not_an_integer("bit"),
+    };
+
+    match index.to_u64() {
+        Some(index) => Object::Integer(int.bit(index) as i64),
+        None => out_of_domain("bit"),
+    }
+}
@@ -0,0 +1,217 @@
+use calc_rs::engine::{InfoType, Object};
+use PROMPT;
+use stdweb::{traits::*,
+             unstable::TryInto,
+             web::{document, HtmlElement}};
+
+/// Builds the DOM that shows `object` on a line of the console, or `None` if
+/// there is nothing to show. `input` is the line that produced it.
+pub fn display(object: Object, input: &str) -> Option<HtmlElement> {
+    // A macro to create `p` elements.
+    macro_rules! new_text_node {
+        ($text:expr) => {{
+            let display: HtmlElement = document().create_element("p").unwrap().try_into().unwrap();
+            display.append_child(&document().create_text_node($text));
+            display
+        }};
+    }
+
+    // A macro to create links with specified text and location.
+    macro_rules! new_link_node {
+        ($href:expr, $text:expr) => {{
+            let link: HtmlElement = document().create_element("a").unwrap().try_into().unwrap();
+            link.append_child(&document().create_text_node($text));
+            link.set_attribute("href", $href).unwrap();
+            link
+        }};
+    }
+
+    match object {
+        Object::Integer(int) => Some(new_text_node!(&int.to_string())),
+        Object::BigInteger(int) => Some(new_text_node!(&int.to_string())),
+        Object::Rational(ratio) => Some(new_text_node!(&ratio.to_string())),
+        Object::Float(float) => Some(new_text_node!(&float.to_string())),
+        complex @ Object::Complex(_) => Some(new_text_node!(&complex.to_string())),
+        Object::Quantity(quantity) => Some(new_text_node!(&quantity.to_string())),
+        based @ Object::InBase(..) => Some(new_text_node!(&based.to_string())),
+        Object::Boolean(boolean) => Some(new_text_node!(&boolean.to_string())),
+        list @ Object::List(_) => Some(new_text_node!(&list.to_string())),
+        matrix @ Object::Matrix(_) => Some(new_text_node!(&matrix.to_string())),
+        Object::Symbolic(symbolic) => Some(new_text_node!(&symbolic.to_string())),
+        Object::Plot(plot) => {
+            // The plot is already an SVG document, so it is put in as is.
+            let container: HtmlElement = document()
+                .create_element("div")
+                .unwrap()
+                .try_into()
+                .unwrap();
+            let svg = plot.to_string();
+            js! { @(no_return) @{&container}.innerHTML = @{svg}; }
+            container.class_list().add("plot").unwrap();
+            Some(container)
+        }
+        Object::Error(error) => {
+            let container: HtmlElement = document()
+                .create_element("div")
+                .unwrap()
+                .try_into()
+                .unwrap();
+
+            // Line up carets under the part of the input that went wrong.
+            if let Some(underline) = error.underline(input) {
+                let carets: HtmlElement = document()
+                    .create_element("pre")
+                    .unwrap()
+                    .try_into()
+                    .unwrap();
+                carets.append_child(&document().create_text_node(&format!(
+                    "{}{}",
+                    " ".repeat(PROMPT.chars().count()),
+                    underline
+                )));
+                container.append_child(&carets);
+            }
+
+            container.append_child(&new_text_node!(&error.to_string()));
+            container.class_list().add("error").unwrap();
+            Some(container)
+        }
+        Object::Info(InfoType::About) => {
+            let container: HtmlElement = document()
+                .create_element("div")
+                .unwrap()
+                .try_into()
+                .unwrap();
+            let display1 = new_text_node!(
+                "This is a REPL calculator running on the web. It was made with the "
+            );
+            display1.append_child(&new_link_node!(
+                "https://www.rust-lang.org",
+                "Rust programming language"
+            ));
+            display1.append_child(&document().create_text_node(" and "));
+            display1.append_child(&new_link_node!("https://github.com/koute/stdweb", "stdweb"));
+            display1.append_child(&document().create_text_node(", a library for constructing client side web apps in Rust. It was compiled to "));
+            display1.append_child(&new_link_node!(
+                "https://developer.mozilla.org/en-US/docs/WebAssembly",
+                "WebAssembly"
+            ));
+            display1.append_child(&document().create_text_node(" and then included in an html file to run on the web. To see the source code, check it out on "));
+            display1.append_child(&new_link_node!(
+                "https://github.com/cgm616/calc_rs",
+                "Github."
+            ));
+            let line_break: HtmlElement =
+                document().create_element("br").unwrap().try_into().unwrap();
+            let display2 = new_text_node!("Try running `help()` for more info.");
+
+            container.append_child(&display1);
+            container.append_child(&line_break);
+            container.append_child(&display2);
+            container.class_list().add("info").unwrap();
+            Some(container)
+        }
+        Object::Info(InfoType::Help) => {
+            let container: HtmlElement = document()
+                .create_element("div")
+                .unwrap()
+                .try_into()
+                .unwrap();
+            let display1 = new_text_node!("Use any of the following operations:");
+            display1.append_child::<HtmlElement>(&document()
+                .create_element("br")
+                .unwrap()
+                .try_into()
+                .unwrap());
+            display1.append_child(&document().create_text_node("+ for addition"));
+            display1.append_child::<HtmlElement>(&document()
+                .create_element("br")
+                .unwrap()
+                .try_into()
+                .unwrap());
+            display1.append_child(&document().create_text_node("- for subtraction or negation (ex: `-x`)"));
+            display1.append_child::<HtmlElement>(&document()
+                .create_element("br")
+                .unwrap()
+                .try_into()
+                .unwrap());
+            display1.append_child(&document().create_text_node("* for multiplication"));
+            display1.append_child::<HtmlElement>(&document()
+                .create_element("br")
+                .unwrap()
+                .try_into()
+                .unwrap());
+            display1.append_child(&document().create_text_node("/ for division"));
+            display1.append_child::<HtmlElement>(&document()
+                .create_element("br")
+                .unwrap()
+                .try_into()
+                .unwrap());
+            display1.append_child(&document().create_text_node("^ for exponentation"));
+            display1.append_child::<HtmlElement>(&document()
+                .create_element("br")
+                .unwrap()
+                .try_into()
+                .unwrap());
+            display1.append_child(&document().create_text_node("&, |, xor and ~ for bitwise and, or, exclusive or and not"));
+            display1.append_child::<HtmlElement>(&document()
+                .create_element("br")
+                .unwrap()
+                .try_into()
+                .unwrap());
+            display1.append_child(&document().create_text_node("<< and >> for shifting bits left and right"));
+            display1.append_child::<HtmlElement>(&document()
+                .create_element("br")
+                .unwrap()
+                .try_into()
+                .unwrap());
+            display1.append_child(&document().create_text_node("==, !=, <, <=, > and >= for comparisons (ex: `x > 0.3`)"));
+            display1.append_child::<HtmlElement>(&document()
+                .create_element("br")
+                .unwrap()
+                .try_into()
+                .unwrap());
+            display1.append_child(&document().create_text_node("and, or and not for combining comparisons"));
+            display1.append_child::<HtmlElement>(&document()
+                .create_element("br")
+                .unwrap()
+                .try_into()
+                .unwrap());
+            display1.append_child(&document().create_text_node("if, then and else for choosing (ex: `if x > 0 then x else -x`)"));
+            display1.append_child::<HtmlElement>(&document()
+                .create_element("br")
+                .unwrap()
+                .try_into()
+                .unwrap());
+            display1
+                .append_child(&document().create_text_node("= for assignment of variables (ex: `a = b`)"));
+            display1.append_child::<HtmlElement>(&document()
+                .create_element("br")
+                .unwrap()
+                .try_into()
+                .unwrap());
+            display1.append_child(&document().create_text_node("; for doing several things at once (ex: `a = 2; b = 3; a * b`)"));
+            display1.append_child::<HtmlElement>(&document()
+                .create_element("br")
+                .unwrap()
+                .try_into()
+                .unwrap());
+            display1.append_child(&document().create_text_node("# or /* */ for comments (ex: `g = 9.81 # at sea level`)"));
+            let line_break1: HtmlElement =
+                document().create_element("br").unwrap().try_into().unwrap();
+            let functions = new_text_node!("Call functions like `sqrt(2)` or `log(8, 2)`. The built in functions are sqrt, abs, sin, cos, tan, asin, acos, atan, ln, log10, log, exp, floor, ceil, round, min, max, gcd, lcm, popcount, bit, sum, product, mean, len, transpose, det, inverse, rank and linsolve. Define your own with `f(x, y) = x^2 + y`.");
+            let line_break2: HtmlElement =
+                document().create_element("br").unwrap().try_into().unwrap();
+            let display2 = new_text_node!("Try using a few well known constants, like `pi` and `e`. `ans` is a special variable that is always the last result. Write imaginary numbers with an `i`, like `3 + 4i`. Put units after numbers, like `60 mph` or `9.8 m/s^2`, and convert between them with `to`, like `5 km to mi`. Integers can be written in hex, octal or binary, like `0x1F`, `0o17` or `0b1011`, and shown that way with `to hex`, `to oct` or `to bin`. Make lists like `v = [1, 2, 3]`, get their elements like `v[0]`, and do arithmetic on every element at once, like `v * 2`. Lists of rows make matrices, like `A = [[1, 2], [3, 4]]`, where `A * B` is the matrix product and `linsolve(A, b)` solves `A x = b`. Differentiate with `diff`, like `d = diff(x^3, x)`. The result stays as `3 * x^2` until `x` has a value, like in `x = 2; d`. Find where an equation holds with `solve`, like `solve(x^2 = 2, x)`, which can be given a guess or a range to look in after `x`, or where a function is zero between two values with `root`, like `root(sin, 3, 4)`. Integrate with `integrate(x^2, x, 0, 1)`, and add up or multiply a series with `sum(1/k^2, k, 1, 10)` or `prod(k, k, 1, 5)`. Draw a chart of an expression with `plot(sin(x), x, 0, 2pi)`, or of several at once with `plot([sin(x), cos(x)], x, 0, 2pi)`.");
+
+            container.append_child(&display1);
+            container.append_child(&line_break1);
+            container.append_child(&functions);
+            container.append_child(&line_break2);
+            container.append_child(&display2);
+            container.class_list().add("info").unwrap();
+            Some(container)
+        }
+        Object::Nil => None,
+    }
+}
@@ -0,0 +1,2454 @@
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_traits::ToPrimitive;
+use pest::{iterators::Pair,
+           prec_climber::{Assoc, Operator, PrecClimber},
+           Parser};
+use std::{cell::{Cell, RefCell},
+          collections::HashMap,
+          ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Rem, Shl, Shr, Sub}};
+
+use builtins::{self, Arity};
+use numeric::{self, Sample};
+pub use model::{Base, CalcError, Comparison, Expression, Function, InfoType, Object, Quantity,
+                Plot, Series, Span, State, Symbolic, Unit};
+pub use parse::{CalcParser, Rule};
+
+lazy_static! {
+    static ref PREC_CLIMBER: PrecClimber<Rule> = PrecClimber::new(vec![
+        Operator::new(Rule::or, Assoc::Left),
+        Operator::new(Rule::and, Assoc::Left),
+        Operator::new(Rule::eq, Assoc::Left)
+            | Operator::new(Rule::ne, Assoc::Left)
+            | Operator::new(Rule::lt, Assoc::Left)
+            | Operator::new(Rule::le, Assoc::Left)
+            | Operator::new(Rule::gt, Assoc::Left)
+            | Operator::new(Rule::ge, Assoc::Left),
+        Operator::new(Rule::bit_or, Assoc::Left),
+        Operator::new(Rule::bit_xor, Assoc::Left),
+        Operator::new(Rule::bit_and, Assoc::Left),
+        Operator::new(Rule::shl, Assoc::Left) | Operator::new(Rule::shr, Assoc::Left),
+        Operator::new(Rule::sub, Assoc::Left) | Operator::new(Rule::add, Assoc::Left),
+        Operator::new(Rule::mul, Assoc::Left) | Operator::new(Rule::div, Assoc::Left),
+        Operator::new(Rule::rem, Assoc::Left),
+    ]);
+}
+
+// Returns early from `consume` if an operand is already an error.
+macro_rules! try_object {
+    ($object:expr) => {
+        match $object {
+            error @ Object::Error(_) => return error,
+            object => object,
+        }
+    };
+}
+
+/// How deeply calls to user defined functions can nest before giving up.
+const MAX_DEPTH: usize = 128;
+
+/// How many terms `sum(expr, k, from, to)` and `prod` go through at most.
+const MAX_TERMS: usize = 1_000_000;
+
+/// How large the denominator of an exact sum or product can get before it is
+/// turned into a float.
+const MAX_DENOMINATOR_BITS: u64 = 256;
+
+/// Functions that work on an expression rather than its value, like
+/// `diff(x^2, x)` or `solve(x^2 = 2, x)`, so their arguments aren't evaluated
+/// before the call. They live here rather than with the other built in
+/// functions because they need the parse tree.
+const SPECIAL_FUNCTIONS: &[&str] = &["diff", "solve", "root", "integrate", "prod", "plot"];
+
+/// Parses and evaluates a single line of input against `state`, returning
+/// the resulting `Object`. Assignments are written into `state`.
+///
+/// A line can hold several statements separated by `;`, in which case the
+/// result is that of the last one, or the first error.
+pub fn evaluate(state: &mut State, input: &str) -> Object {
+    evaluate_each(state, input).pop().unwrap()
+}
+
+/// Like `evaluate`, but returns the result of every statement in the line,
+/// in order. Evaluation stops at the first error, which is the last result.
+/// `ans` is updated between statements, so `2 + 3; ans * 2` is `10`.
+pub fn evaluate_each(state: &mut State, input: &str) -> Vec<Object> {
+    // follows P E (M | D) (A | S)
+
+    let pairs = match CalcParser::parse(Rule::program, input) {
+        Ok(pairs) => pairs,
+        Err(error) => return vec![Object::Error(parse_error(&error))],
+    };
+
+    // TODO: order of ops working, responsive design, make help
+    // messages work better, comment code, add desktop gui, etc.
+    fn consume(state: &mut State, pair: Pair<Rule>) -> Object {
+        match pair.as_rule() {
+            Rule::assn => assignment(state, pair),
+            Rule::def => definition(state, pair),
+            Rule::expr | Rule::relation => operators(state, pair),
+            Rule::power => power(state, pair),
+            Rule::call => function_call(state, pair),
+            Rule::list => list(state, pair),
+            Rule::index => index(state, pair),
+            Rule::neg | Rule::bit_not | Rule::logical_not => unary(state, pair),
+            Rule::pos => consume(state, pair.into_inner().next().unwrap()),
+            Rule::symbol => variable(state, pair),
+            Rule::conditional => conditional(state, pair),
+            Rule::quantity => quantity(state, pair),
+            Rule::convert => convert(state, pair),
+            Rule::boolean => Object::Boolean(pair.as_str() == "true"),
+            Rule::int => locate(parse_int(pair.as_str()), &pair),
+            Rule::float => locate(pair.as_str().parse::<f64>().unwrap().into(), &pair),
+            Rule::imaginary => {
+                let coefficient = pair.as_str().trim_end_matches('i');
+                let coefficient = if coefficient.is_empty() {
+                    1.0
+                } else if coefficient.contains('.') {
+                    coefficient.parse::<f64>().unwrap()
+                } else {
+                    // Integers can be written in other bases or with `_`.
+                    match parse_int(coefficient) {
+                        error @ Object::Error(_) => return locate(error, &pair),
+                        int => int.as_float().unwrap(),
+                    }
+                };
+                locate(Complex64::new(0.0, coefficient).into(), &pair)
+            }
+            Rule::help => Object::Info(InfoType::Help),
+            Rule::about => Object::Info(InfoType::About),
+            _ => unreachable!(),
+        }
+    }
+
+    // Everything but the simplest rules lives outside of `consume` to keep
+    // its stack frame small, since it recurses at least once per nested call.
+    fn assignment(state: &mut State, pair: Pair<Rule>) -> Object {
+        // In an assignment, there must be exactly 2 pairs: `a = b`,
+        // where a is a symbol and b is some kind of expression.
+        let mut inner = pair.into_inner();
+        let left = inner.next().unwrap(); // symbol
+        if let Some(error) = imaginary_unit(&left) {
+            return error;
+        }
+        let right = consume(state, inner.next().unwrap()); // expr
+
+        if let Object::Error(_) = right {
+            return right; // Don't assign errors to anything.
+        }
+
+        state // Insert the assignment
+            .assignments
+            .insert(left.as_str().to_string(), right);
+        Object::Nil // and return nil to the machine.
+    }
+
+    fn definition(state: &mut State, pair: Pair<Rule>) -> Object {
+        // A definition is the name of the function, its parameters,
+        // and then the body, which is stored without evaluating it.
+        let mut inner = pair.into_inner();
+        let name_pair = inner.next().unwrap();
+        let name = name_pair.as_str().to_string();
+        let mut params: Vec<String> = Vec::new();
+        let mut body = "";
+
+        for pair in inner {
+            match pair.as_rule() {
+                Rule::symbol if params.iter().any(|param| param == pair.as_str()) => {
+                    return Object::Error(CalcError::Definition {
+                        message: format!(
+                            "{} is used as a parameter more than once",
+                            pair.as_str()
+                        ),
+                        span: Some(span_of(&pair)),
+                    })
+                }
+                Rule::symbol => match imaginary_unit(&pair) {
+                    Some(error) => return error,
+                    None => params.push(pair.as_str().to_string()),
+                },
+                _ => body = pair.as_str(),
+            }
+        }
+
+        if builtins::find(&name).is_some() || SPECIAL_FUNCTIONS.contains(&name.as_str()) {
+            return Object::Error(CalcError::Definition {
+                message: format!("{} is a built in function", name),
+                span: Some(span_of(&name_pair)),
+            });
+        }
+
+        state.functions.insert(
+            name,
+            Function {
+                params,
+                body: body.to_string(),
+            },
+        );
+        Object::Nil
+    }
+
+    fn operators(state: &mut State, pair: Pair<Rule>) -> Object {
+        let primary = |pair| consume(state, pair);
+
+        // Errors from either side are passed along untouched, and new
+        // ones point at the operator.
+        let infix = |lhs: Object, op: Pair<Rule>, rhs: Object| {
+            let result = match (lhs, rhs) {
+                (error @ Object::Error(_), _) | (_, error @ Object::Error(_)) => {
+                    return error
+                }
+                (lhs, rhs) => match op.as_rule() {
+                    Rule::add => lhs.add(rhs),
+                    Rule::sub => lhs.sub(rhs),
+                    Rule::mul => lhs.mul(rhs),
+                    Rule::div => lhs.div(rhs),
+                    Rule::rem => lhs.rem(rhs),
+                    Rule::shl => lhs.shl(rhs),
+                    Rule::shr => lhs.shr(rhs),
+                    Rule::bit_and => lhs.bitand(rhs),
+                    Rule::bit_or => lhs.bitor(rhs),
+                    Rule::bit_xor => lhs.bitxor(rhs),
+                    Rule::eq => lhs.compare_with(Comparison::Equal, rhs),
+                    Rule::ne => lhs.compare_with(Comparison::NotEqual, rhs),
+                    Rule::lt => lhs.compare_with(Comparison::Less, rhs),
+                    Rule::le => lhs.compare_with(Comparison::LessOrEqual, rhs),
+                    Rule::gt => lhs.compare_with(Comparison::Greater, rhs),
+                    Rule::ge => lhs.compare_with(Comparison::GreaterOrEqual, rhs),
+                    Rule::and => lhs.and(rhs),
+                    Rule::or => lhs.or(rhs),
+                    _ => unreachable!(),
+                },
+            };
+            locate(result, &op)
+        };
+
+        PREC_CLIMBER.climb(pair.into_inner(), primary, infix)
+    }
+
+    fn power(state: &mut State, pair: Pair<Rule>) -> Object {
+        // A power is `a ^ b`, where b may itself be a power.
+        let mut inner = pair.into_inner();
+        let base = try_object!(consume(state, inner.next().unwrap()));
+        let op = inner.next().unwrap(); // pow
+        let exponent = try_object!(consume(state, inner.next().unwrap()));
+        locate(base.pow(exponent), &op)
+    }
+
+    fn function_call(state: &mut State, pair: Pair<Rule>) -> Object {
+        // A call is the name of the function followed by each of its
+        // arguments.
+        let span = span_of(&pair);
+        let mut inner = pair.into_inner();
+        let name_pair = inner.next().unwrap();
+        let name = name_pair.as_str();
+        let args: Vec<Pair<Rule>> = inner.collect();
+        if name != "solve" {
+            if let Some(equals) = args.iter().find(|arg| arg.as_rule() == Rule::equals) {
+                return Object::Error(
+                    CalcError::type_mismatch("only solve can be given an equation")
+                        .at(span_of(equals)),
+                );
+            }
+        }
+        match name {
+            "diff" => return differentiate(state, span, name_pair, args),
+            "solve" => return solve(state, &name_pair, args),
+            "root" => return root(state, &name_pair, args),
+            "integrate" => return integrate(state, &name_pair, args),
+            "prod" => return series(state, &name_pair, args),
+            "plot" => return plot(state, &name_pair, args),
+            // `sum(k^2, k, 1, 10)` counts with a variable that doesn't have a
+            // value yet. Given anything else, `sum` adds up its arguments.
+            "sum" if args.len() == 4 => match variable_name(&args[1]) {
+                Some(ref variable) if !state.assignments.contains_key(variable) => {
+                    return series(state, &name_pair, args)
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+
+        let args = {
+            let mut values = Vec::new();
+            for arg in args {
+                values.push(try_object!(consume(state, arg)));
+            }
+            values
+        };
+
+        match state.functions.get(name).cloned() {
+            // Errors inside the body point into the body's source, so
+            // point them at the call instead.
+            Some(function) => match call(state, name, &function, args) {
+                Object::Error(error) => Object::Error(error.at(span)),
+                result => result,
+            },
+            None => match builtins::find(name) {
+                Some(builtin) => locate(builtin.call(args), &name_pair),
+                None => Object::Error(CalcError::UnknownFunction {
+                    name: name.to_string(),
+                    span: Some(span_of(&name_pair)),
+                }),
+            },
+        }
+    }
+
+    // Variables come first, so that a variable named `m` can still be used on
+    // its own, and units are a fallback.
+    fn variable(state: &mut State, pair: Pair<Rule>) -> Object {
+        look_up(state, pair.as_str(), span_of(&pair))
+    }
+
+    fn look_up(state: &mut State, name: &str, span: Span) -> Object {
+        match state.assignments.get(name) {
+            Some(Object::Symbolic(symbolic))
+                if state.assignments.contains_key(&symbolic.variable) =>
+            {
+                let symbolic = symbolic.clone();
+                evaluate_symbolic(state, &symbolic, span)
+            }
+            Some(obj) => obj.clone(),
+            None => match state.units.get(name) {
+                Some(unit) => Object::Quantity(unit.clone()),
+                None => Object::Error(CalcError::UnknownVariable {
+                    name: name.to_string(),
+                    span: Some(span),
+                }),
+            },
+        }
+    }
+
+    /// Evaluates a symbolic expression now that its variable has a value,
+    /// pointing any errors at `span`.
+    fn evaluate_symbolic(state: &mut State, symbolic: &Symbolic, span: Span) -> Object {
+        if state.depth >= MAX_DEPTH {
+            return Object::Error(
+                CalcError::overflow(format!("{} depends on itself", symbolic.variable)).at(span),
+            );
+        }
+
+        state.depth += 1;
+        let result = expression_value(state, &symbolic.expression, span);
+        state.depth -= 1;
+        match result {
+            Object::Error(error) => Object::Error(error.at(span)),
+            result => result,
+        }
+    }
+
+    /// Works out the value of an expression tree, looking up its symbols the
+    /// same way as variables in the input.
+    fn expression_value(state: &mut State, expression: &Expression, span: Span) -> Object {
+        let (lhs, rhs) = match *expression {
+            Expression::Number(ref number) => return number.clone(),
+            Expression::Symbol(ref name) => return look_up(state, name, span),
+            Expression::Neg(ref inner) => {
+                return try_object!(expression_value(state, inner, span)).neg()
+            }
+            Expression::Call(ref name, ref args) => {
+                let mut values = Vec::new();
+                for arg in args {
+                    values.push(try_object!(expression_value(state, arg, span)));
+                }
+                // Calls to functions the user defined were expanded already.
+                return match builtins::find(name) {
+                    Some(builtin) => builtin.call(values),
+                    None => Object::Error(CalcError::UnknownFunction {
+                        name: name.to_string(),
+                        span: Some(span),
+                    }),
+                };
+            }
+            Expression::Add(ref lhs, ref rhs)
+            | Expression::Sub(ref lhs, ref rhs)
+            | Expression::Mul(ref lhs, ref rhs)
+            | Expression::Div(ref lhs, ref rhs)
+            | Expression::Pow(ref lhs, ref rhs) => (lhs, rhs),
+        };
+        let lhs = try_object!(expression_value(state, lhs, span));
+        let rhs = try_object!(expression_value(state, rhs, span));
+        match *expression {
+            Expression::Add(..) => lhs.add(rhs),
+            Expression::Sub(..) => lhs.sub(rhs),
+            Expression::Mul(..) => lhs.mul(rhs),
+            Expression::Div(..) => lhs.div(rhs),
+            _ => lhs.pow(rhs),
+        }
+    }
+
+    // `diff(expr, x)` works on the expression itself, so it is turned into a
+    // tree instead of being evaluated. The derivative stays symbolic until
+    // `x` has a value.
+    fn differentiate(
+        state: &mut State,
+        span: Span,
+        name_pair: Pair<Rule>,
+        args: Vec<Pair<Rule>>,
+    ) -> Object {
+        let symbolic = match derivative(state, &name_pair, args) {
+            Ok(Symbolic {
+                expression: Expression::Number(number),
+                ..
+            }) => return number,
+            Ok(symbolic) => symbolic,
+            Err(error) => return Object::Error(error),
+        };
+        if state.assignments.contains_key(&symbolic.variable) {
+            evaluate_symbolic(state, &symbolic, span)
+        } else {
+            Object::Symbolic(Box::new(symbolic))
+        }
+    }
+
+    fn derivative(
+        state: &mut State,
+        name_pair: &Pair<Rule>,
+        args: Vec<Pair<Rule>>,
+    ) -> Result<Symbolic, CalcError> {
+        if args.len() != 2 {
+            let error = builtins::wrong_arity("diff", Arity::Exactly(2), args.len());
+            return match locate(error, name_pair) {
+                Object::Error(error) => Err(error),
+                _ => unreachable!(),
+            };
+        }
+        let variable = match variable_name(&args[1]) {
+            Some(variable) => variable,
+            None => {
+                return Err(CalcError::type_mismatch(
+                    "diff has to be given the variable to differentiate by, like `diff(x^2, x)`",
+                ).at(span_of(&args[1])))
+            }
+        };
+        let expression = tree(state, args[0].clone())?
+            .derivative(&variable)
+            .map_err(|error| error.or_at(span_of(name_pair)))?;
+        Ok(Symbolic {
+            expression,
+            variable,
+        })
+    }
+
+    /// Turns an expression into a tree that can be worked with symbolically,
+    /// expanding calls to functions the user defined and variables holding
+    /// symbolic expressions, and working out arithmetic between numbers,
+    /// along the way. Like `consume`, this recurses for
+    /// every call it expands, so anything bigger lives in its own function.
+    fn tree(state: &mut State, pair: Pair<Rule>) -> Result<Expression, CalcError> {
+        match pair.as_rule() {
+            Rule::expr => operators_tree(state, pair),
+            Rule::power => {
+                let mut inner = pair.into_inner();
+                let base = tree(state, inner.next().unwrap())?;
+                let exponent = tree(state, inner.nth(1).unwrap())?;
+                Ok(Expression::power(base, exponent))
+            }
+            Rule::neg => Ok(Expression::negation(tree(state, pair.into_inner().next().unwrap())?)),
+            Rule::pos => tree(state, pair.into_inner().next().unwrap()),
+            Rule::int | Rule::float | Rule::imaginary => {
+                match consume(state, pair) {
+                    Object::Error(error) => Err(error),
+                    number => Ok(Expression::Number(number)),
+                }
+            }
+            Rule::symbol => Ok(match state.assignments.get(pair.as_str()) {
+                Some(Object::Symbolic(symbolic)) => symbolic.expression.clone(),
+                _ => Expression::Symbol(pair.as_str().to_string()),
+            }),
+            Rule::quantity => quantity_tree(state, pair),
+            Rule::call => call_tree(state, pair),
+            _ => Err(not_symbolic(&pair)),
+        }
+    }
+
+    fn operators_tree(state: &mut State, pair: Pair<Rule>) -> Result<Expression, CalcError> {
+        let infix = |lhs: Result<Expression, CalcError>, op: Pair<Rule>, rhs| {
+            let (lhs, rhs) = (lhs?, rhs?);
+            Ok(match op.as_rule() {
+                Rule::add => Expression::sum(lhs, rhs),
+                Rule::sub => Expression::difference(lhs, rhs),
+                Rule::mul => Expression::product(lhs, rhs),
+                Rule::div => Expression::quotient(lhs, rhs),
+                _ => return Err(not_symbolic(&op)),
+            })
+        };
+        PREC_CLIMBER.climb(pair.into_inner(), |pair| tree(state, pair), infix)
+    }
+
+    // A number and its unit, like `3 x^2`, multiplied together.
+    fn quantity_tree(state: &mut State, pair: Pair<Rule>) -> Result<Expression, CalcError> {
+        let mut inner = pair.into_inner();
+        let mut result = tree(state, inner.next().unwrap())?;
+        let mut op = Rule::mul;
+        for pair in inner.next().unwrap().into_inner() {
+            if pair.as_rule() != Rule::unit_power {
+                op = pair.as_rule();
+                continue;
+            }
+            let mut inner = pair.into_inner();
+            let name = inner.next().unwrap().as_str().to_string();
+            let mut factor = Expression::Symbol(name);
+            if let Some(exponent) = inner.nth(1) {
+                let exponent = Expression::Number(parse_int(exponent.as_str()));
+                factor = Expression::power(factor, exponent);
+            }
+            result = match op {
+                Rule::mul => Expression::product(result, factor),
+                _ => Expression::quotient(result, factor),
+            };
+        }
+        Ok(result)
+    }
+
+    fn call_tree(state: &mut State, pair: Pair<Rule>) -> Result<Expression, CalcError> {
+        let span = span_of(&pair);
+        let mut inner = pair.into_inner();
+        let name_pair = inner.next().unwrap();
+        let name = name_pair.as_str();
+        if name == "diff" {
+            let derivative = derivative(state, &name_pair, inner.collect())?;
+            return Ok(derivative.expression);
+        }
+        if SPECIAL_FUNCTIONS.contains(&name) {
+            return Err(not_symbolic(&name_pair).at(span));
+        }
+
+        let mut args = Vec::new();
+        for arg in inner {
+            args.push(tree(state, arg)?);
+        }
+
+        let function = match state.functions.get(name).cloned() {
+            Some(function) => function,
+            None if builtins::find(name).is_some() => {
+                return Ok(Expression::Call(name.to_string(), args))
+            }
+            None => {
+                return Err(CalcError::UnknownFunction {
+                    name: name.to_string(),
+                    span: Some(span_of(&name_pair)),
+                })
+            }
+        };
+
+        if args.len() != function.params.len() {
+            let expected = Arity::Exactly(function.params.len());
+            return match builtins::wrong_arity(name, expected, args.len()) {
+                Object::Error(error) => Err(error.at(span)),
+                _ => unreachable!(),
+            };
+        }
+        if state.depth >= MAX_DEPTH {
+            return Err(CalcError::overflow(format!("too much recursion in {}", name)).at(span));
+        }
+
+        // The body is expanded with the arguments in place of the parameters.
+        let body = parse_body(name, &function.body).map_err(|error| error.at(span))?;
+        state.depth += 1;
+        let body = tree(state, body);
+        state.depth -= 1;
+        let bindings: HashMap<String, Expression> = function.params.into_iter().zip(args).collect();
+        body.map(|body| body.substitute(&bindings))
+            .map_err(|error| error.at(span))
+    }
+
+    // `solve(lhs = rhs, x)` looks for a value of `x` that makes both sides
+    // equal by evaluating them again and again with `x` bound to each guess.
+    // It starts looking from 1, or from a guess given after `x`, or looks
+    // between two given values.
+    fn solve(state: &mut State, name_pair: &Pair<Rule>, args: Vec<Pair<Rule>>) -> Object {
+        if args.len() < 3 || args[1].as_rule() != Rule::equals {
+            let span = args.first().map_or(span_of(name_pair), span_of);
+            return Object::Error(
+                CalcError::type_mismatch(
+                    "solve has to be given an equation, like `solve(x^2 = 2, x)`",
+                ).at(span),
+            );
+        }
+        let (lhs, equals, rhs, rest) = (&args[0], &args[1], &args[2], &args[3..]);
+        if let Some(equals) = rest.iter().find(|arg| arg.as_rule() == Rule::equals) {
+            return Object::Error(
+                CalcError::type_mismatch("solve can only be given one equation")
+                    .at(span_of(equals)),
+            );
+        }
+        if rest.is_empty() || rest.len() > 3 {
+            let error = builtins::wrong_arity("solve", Arity::Between(2, 4), rest.len() + 1);
+            return locate(error, name_pair);
+        }
+        let variable = match variable_name(&rest[0]) {
+            Some(variable) => variable,
+            None => {
+                return Object::Error(
+                    CalcError::type_mismatch(
+                        "solve has to be given the variable to solve for, like `solve(x^2 = 2, x)`",
+                    ).at(span_of(&rest[0])),
+                )
+            }
+        };
+        let mut places = Vec::new();
+        for pair in &rest[1..] {
+            match real_argument(state, pair, "solve") {
+                Ok(place) => places.push(place),
+                Err(error) => return Object::Error(error),
+            }
+        }
+
+        // Along with the difference between the sides, keep track of how big
+        // the sides were the last time, the biggest difference seen, and the
+        // last overflow, if a number was too large somewhere.
+        let sides = Cell::new(0.0);
+        let largest = Cell::new(0f64);
+        let overflow = RefCell::new(None);
+        let span = span_of(equals);
+        let mut difference = |x: f64| {
+            let bindings = vec![(variable.clone(), Object::Float(x))];
+            let (lhs, rhs) = state.with_bindings(bindings, |state| {
+                (consume(state, lhs.clone()), consume(state, rhs.clone()))
+            });
+            let size = |side: &Object| match real_value(side.clone(), "solve") {
+                Ok(Some(value)) => value.abs(),
+                _ => 0.0,
+            };
+            sides.set(size(&lhs).max(size(&rhs)));
+            let difference = match (lhs, rhs) {
+                (error @ Object::Error(_), _) | (_, error @ Object::Error(_)) => error,
+                (lhs, rhs) => lhs - rhs,
+            };
+            match real_value(difference, "solve") {
+                Ok(Some(y)) => {
+                    largest.set(largest.get().max(y.abs()));
+                    Ok(Some(y))
+                }
+                // There could still be a root somewhere the numbers are
+                // smaller, like for `exp(x) = 0`.
+                Err(error @ CalcError::Overflow { .. }) => {
+                    *overflow.borrow_mut() = Some(error.or_at(span));
+                    Ok(None)
+                }
+                result => result.map_err(|error| error.or_at(span)),
+            }
+        };
+        let found = match places[..] {
+            [] => numeric::find_root(&mut difference, 1.0),
+            [guess] => numeric::find_root(&mut difference, guess),
+            [a, b] => root_between(&mut difference, a, b),
+            _ => unreachable!(),
+        };
+        // Where the sides are big enough, adding something small to one of
+        // them doesn't change it, so `x = x + 1` looks like it holds for huge
+        // `x`. A root only counts if the rounding of the sides there is well
+        // short of the differences seen elsewhere. Where they are small
+        // enough, they can both be rounded to zero, so `exp(x) = 0` looks like
+        // it holds for very negative `x`, all the way along.
+        let found = found.and_then(|root| match root {
+            Some(root) => {
+                difference(root)?;
+                let rounding = 4.0 * f64::EPSILON * sides.get();
+                let absorbed = largest.get() != 0.0 && rounding >= largest.get();
+                let step = 1e-6 * root.abs().max(1.0);
+                let underflowed = sides.get() == 0.0
+                    && difference(root - step)? == Some(0.0)
+                    && difference(root + step)? == Some(0.0);
+                Ok(if absorbed || underflowed { None } else { Some(root) })
+            }
+            None => Ok(None),
+        });
+
+        let span = span_of(name_pair);
+        let message = format!(
+            "solve couldn't find a value of {} that solves the equation",
+            variable
+        );
+        let missing = || match overflow.borrow_mut().take() {
+            // Nowhere had a value at all, so the overflow is all there is.
+            Some(error) if largest.get() == 0.0 => error,
+            _ => CalcError::domain(message).at(span),
+        };
+        match found.map_err(|error| error.or_at(span)).and_then(|root| match root {
+            Some(root) => numeric::tidy(&mut difference, root),
+            None => Err(missing()),
+        }) {
+            Ok(root) => Object::Float(root),
+            Err(error) => Object::Error(error),
+        }
+    }
+
+    // `root(f, a, b)` finds where a function of one argument is zero between
+    // `a` and `b`, calling it just like a call in an expression would.
+    fn root(state: &mut State, name_pair: &Pair<Rule>, args: Vec<Pair<Rule>>) -> Object {
+        if args.len() != 3 {
+            return locate(builtins::wrong_arity("root", Arity::Exactly(3), args.len()), name_pair);
+        }
+        let name = match variable_name(&args[0]) {
+            Some(name) => name,
+            None => {
+                return Object::Error(
+                    CalcError::type_mismatch(
+                        "root has to be given the name of a function, like `root(f, 0, 1)`",
+                    ).at(span_of(&args[0])),
+                )
+            }
+        };
+        let function = state.functions.get(&name).cloned();
+        let builtin = builtins::find(&name);
+        if function.is_none() && builtin.is_none() {
+            return Object::Error(CalcError::UnknownFunction {
+                name,
+                span: Some(span_of(&args[0])),
+            });
+        }
+        let (a, b) = match (
+            real_argument(state, &args[1], "root"),
+            real_argument(state, &args[2], "root"),
+        ) {
+            (Ok(a), Ok(b)) => (a, b),
+            (Err(error), _) | (_, Err(error)) => return Object::Error(error),
+        };
+
+        let span = span_of(&args[0]);
+        let mut value = |x: f64| {
+            let value = match function {
+                Some(ref function) => call(state, &name, function, vec![Object::Float(x)]),
+                None => builtin.unwrap().call(vec![Object::Float(x)]),
+            };
+            real_value(value, "root").map_err(|error| error.at(span))
+        };
+        let message = format!("root couldn't find where {} is zero", name);
+        let span = span_of(name_pair);
+        let found = root_between(&mut value, a, b).map_err(|error| error.or_at(span));
+        match found.and_then(|root| match root {
+            Some(root) => numeric::tidy(&mut value, root),
+            None => Err(CalcError::domain(message).at(span)),
+        }) {
+            Ok(root) => Object::Float(root),
+            Err(error) => Object::Error(error),
+        }
+    }
+
+    // `integrate(expr, x, a, b)` is the area under `expr` as `x` goes from `a`
+    // to `b`, evaluating it wherever the quadrature asks for.
+    fn integrate(state: &mut State, name_pair: &Pair<Rule>, args: Vec<Pair<Rule>>) -> Object {
+        if args.len() != 4 {
+            let error = builtins::wrong_arity("integrate", Arity::Exactly(4), args.len());
+            return locate(error, name_pair);
+        }
+        let variable = match variable_name(&args[1]) {
+            Some(variable) => variable,
+            None => {
+                return Object::Error(
+                    CalcError::type_mismatch(
+                        "integrate has to be given the variable to integrate over, \
+                         like `integrate(x^2, x, 0, 1)`",
+                    ).at(span_of(&args[1])),
+                )
+            }
+        };
+        let (a, b) = match (
+            real_argument(state, &args[2], "integrate"),
+            real_argument(state, &args[3], "integrate"),
+        ) {
+            (Ok(a), Ok(b)) => (a, b),
+            (Err(error), _) | (_, Err(error)) => return Object::Error(error),
+        };
+        if !a.is_finite() || !b.is_finite() {
+            return Object::Error(
+                CalcError::domain("integrate can only go between finite bounds")
+                    .at(span_of(name_pair)),
+            );
+        }
+
+        let (expression, span) = (&args[0], span_of(&args[0]));
+        let mut integrand = |x: f64| {
+            let bindings = vec![(variable.clone(), Object::Float(x))];
+            let value = state.with_bindings(bindings, |state| consume(state, expression.clone()));
+            let real = match value {
+                Object::Error(error) => return Err(error),
+                Object::Complex(complex) if complex.im == 0.0 => Some(complex.re),
+                Object::Complex(_) => None,
+                value => match value.as_float() {
+                    Some(value) => Some(value),
+                    None => {
+                        return Err(
+                            CalcError::type_mismatch("integrate only works with numbers").at(span)
+                        )
+                    }
+                },
+            };
+            match real {
+                Some(value) if value.is_finite() => Ok(value),
+                _ => Err(CalcError::domain(format!(
+                    "that isn't a finite real number at {} = {}",
+                    variable,
+                    Object::Float(x)
+                )).at(span)),
+            }
+        };
+
+        match numeric::integrate(&mut integrand, a, b) {
+            Ok(Some(area)) => Object::Float(numeric::snap(area)),
+            Ok(None) => Object::Error(
+                CalcError::domain("integrate couldn't find the area, since it doesn't settle down")
+                    .at(span_of(name_pair)),
+            ),
+            Err(error) => Object::Error(error.or_at(span_of(name_pair))),
+        }
+    }
+
+    // `sum(expr, k, from, to)` and `prod(expr, k, from, to)` evaluate `expr`
+    // for each integer `k` from `from` to `to`, and add or multiply the terms
+    // together. Exact terms stay exact, so `sum(1/k^2, k, 1, 3)` is `49/36`.
+    fn series(state: &mut State, name_pair: &Pair<Rule>, args: Vec<Pair<Rule>>) -> Object {
+        let name = name_pair.as_str();
+        if args.len() != 4 {
+            return locate(builtins::wrong_arity(name, Arity::Exactly(4), args.len()), name_pair);
+        }
+        let variable = match variable_name(&args[1]) {
+            Some(variable) => variable,
+            None => {
+                return Object::Error(
+                    CalcError::type_mismatch(format!(
+                        "{} has to be given the variable to count with, like `{}(k^2, k, 1, 10)`",
+                        name, name
+                    )).at(span_of(&args[1])),
+                )
+            }
+        };
+        let (from, to) = match (
+            integer_argument(state, &args[2], name),
+            integer_argument(state, &args[3], name),
+        ) {
+            (Ok(from), Ok(to)) => (from, to),
+            (Err(error), _) | (_, Err(error)) => return Object::Error(error),
+        };
+        if i128::from(to) - i128::from(from) >= MAX_TERMS as i128 {
+            return Object::Error(
+                CalcError::overflow(format!(
+                    "{} can only go through {} terms at a time",
+                    name, MAX_TERMS
+                )).at(span_of(name_pair)),
+            );
+        }
+
+        let (empty, combine): (Object, fn(Object, Object) -> Object) = if name == "sum" {
+            (Object::Integer(0), Add::add)
+        } else {
+            (Object::Integer(1), Mul::mul)
+        };
+        // Terms are combined with each other rather than with `empty`, so
+        // that a sum of quantities works, and `empty` is only the result when
+        // counting from after `to` goes through no terms at all.
+        let mut result = None;
+        for k in from..=to {
+            let bindings = vec![(variable.clone(), Object::Integer(k))];
+            let term = state.with_bindings(bindings, |state| consume(state, args[0].clone()));
+            let term = try_object!(term);
+            let combined = match result {
+                Some(result) => locate(combine(result, term), name_pair),
+                None => term,
+            };
+            result = Some(match combined {
+                error @ Object::Error(_) => return error,
+                // Every term that grows an exact fraction's denominator makes
+                // the next one slower, so a long series carries on in floats.
+                Object::Rational(ref ratio) if ratio.denom().bits() > MAX_DENOMINATOR_BITS => {
+                    Object::Float(ratio.to_f64().unwrap())
+                }
+                combined => combined,
+            });
+        }
+        result.unwrap_or(empty)
+    }
+
+    // `plot(expr, x, from, to)` evaluates `expr` at evenly spaced values of
+    // `x` from `from` to `to`. Each expression in a list, like
+    // `[sin(x), cos(x)]`, is evaluated on its own and gets its own line.
+    fn plot(state: &mut State, name_pair: &Pair<Rule>, args: Vec<Pair<Rule>>) -> Object {
+        if args.len() != 4 {
+            return locate(builtins::wrong_arity("plot", Arity::Exactly(4), args.len()), name_pair);
+        }
+        let variable = match variable_name(&args[1]) {
+            Some(variable) => variable,
+            None => {
+                return Object::Error(
+                    CalcError::type_mismatch(
+                        "plot has to be given the variable to plot against, \
+                         like `plot(x^2, x, 0, 1)`",
+                    ).at(span_of(&args[1])),
+                )
+            }
+        };
+        let (from, to) = match (
+            real_argument(state, &args[2], "plot"),
+            real_argument(state, &args[3], "plot"),
+        ) {
+            (Ok(from), Ok(to)) => (from, to),
+            (Err(error), _) | (_, Err(error)) => return Object::Error(error),
+        };
+        if !from.is_finite() || !to.is_finite() || from == to {
+            return Object::Error(
+                CalcError::domain("plot needs two different, finite ends to plot between")
+                    .at(span_of(name_pair)),
+            );
+        }
+
+        let mut plot = Plot {
+            variable: variable.clone(),
+            from,
+            to,
+            series: Vec::new(),
+        };
+        let points = plot.points();
+        let expressions = list_items(&args[0]).unwrap_or_else(|| vec![args[0].clone()]);
+        for expression in expressions {
+            let span = span_of(&expression);
+            let mut values = Vec::new();
+            for &x in &points {
+                let bindings = vec![(variable.clone(), Object::Float(x))];
+                let value =
+                    state.with_bindings(bindings, |state| consume(state, expression.clone()));
+                match real_value(value, "plot") {
+                    Ok(value) => values.push(value),
+                    Err(error) => return Object::Error(error.or_at(span)),
+                }
+            }
+            plot.series.push(Series {
+                label: expression.as_str().trim().to_string(),
+                values,
+            });
+        }
+
+        let values = plot.series.iter().flat_map(|series| series.values.iter());
+        if values.flatten().next().is_none() {
+            return Object::Error(
+                CalcError::domain(format!(
+                    "there's nothing to plot, since that isn't a real number anywhere \
+                     from {} to {}",
+                    Object::Float(from),
+                    Object::Float(to)
+                )).at(span_of(&args[0])),
+            );
+        }
+        Object::Plot(plot)
+    }
+
+    /// Evaluates an argument saying where `sum` or `prod` should count from
+    /// or to.
+    fn integer_argument(
+        state: &mut State,
+        pair: &Pair<Rule>,
+        name: &str,
+    ) -> Result<i64, CalcError> {
+        match consume(state, pair.clone()) {
+            Object::Error(error) => Err(error),
+            Object::Integer(int) => Ok(int),
+            _ => Err(CalcError::type_mismatch(format!("{} can only count between integers", name))
+                .at(span_of(pair))),
+        }
+    }
+
+    /// Evaluates an argument saying where `solve`, `root` or `integrate`
+    /// should look.
+    fn real_argument(state: &mut State, pair: &Pair<Rule>, name: &str) -> Result<f64, CalcError> {
+        match consume(state, pair.clone()) {
+            Object::Error(error) => Err(error),
+            object => object.as_float().ok_or_else(|| {
+                CalcError::type_mismatch(format!("{} can only look between real numbers", name))
+                    .at(span_of(pair))
+            }),
+        }
+    }
+
+    fn list(state: &mut State, pair: Pair<Rule>) -> Object {
+        let mut items = Vec::new();
+        for item in pair.into_inner() {
+            items.push(try_object!(consume(state, item)));
+        }
+        items.into()
+    }
+
+    fn index(state: &mut State, pair: Pair<Rule>) -> Object {
+        // The indexed value comes first, then each index in turn.
+        let mut inner = pair.into_inner();
+        let mut result = try_object!(consume(state, inner.next().unwrap()));
+        for index_pair in inner {
+            let index = try_object!(consume(state, index_pair.clone()));
+            result = try_object!(locate(result.index(index), &index_pair));
+        }
+        result
+    }
+
+    fn unary(state: &mut State, pair: Pair<Rule>) -> Object {
+        let span = span_of(&pair);
+        let rule = pair.as_rule();
+        // The operand comes after the `not` keyword, if there is one.
+        let inner = try_object!(consume(state, pair.into_inner().last().unwrap()));
+        let result = match rule {
+            Rule::neg => inner.neg(),
+            Rule::bit_not => inner.not(),
+            _ => inner.logical_not(),
+        };
+        match result {
+            Object::Error(error) => Object::Error(error.or_at(span)),
+            result => result,
+        }
+    }
+
+    // Only the branch that is taken is evaluated, so errors in the other one
+    // don't matter, and a function can safely call itself in one of them.
+    fn conditional(state: &mut State, pair: Pair<Rule>) -> Object {
+        let mut inner = pair
+            .into_inner()
+            .filter(|pair| !matches!(pair.as_rule(), Rule::if_ | Rule::then | Rule::else_));
+        let condition_pair = inner.next().unwrap();
+        let span = span_of(&condition_pair);
+        let condition = try_object!(consume(state, condition_pair));
+        let taken = match condition.truth() {
+            Some(true) => inner.next(),
+            Some(false) => inner.nth(1),
+            None => {
+                return Object::Error(
+                    CalcError::type_mismatch(
+                        "the condition has to be true, false or a number without units",
+                    ).at(span),
+                )
+            }
+        };
+        consume(state, taken.unwrap())
+    }
+
+    fn quantity(state: &mut State, pair: Pair<Rule>) -> Object {
+        let mut inner = pair.into_inner();
+        let number = try_object!(consume(state, inner.next().unwrap()));
+        let unit_pair = inner.next().unwrap();
+        let span = span_of(&unit_pair);
+        let unit = try_object!(unit(state, unit_pair));
+        match number * unit {
+            Object::Error(error) => Object::Error(error.or_at(span)),
+            result => result,
+        }
+    }
+
+    fn convert(state: &mut State, pair: Pair<Rule>) -> Object {
+        let mut inner = pair.into_inner();
+        let value = try_object!(consume(state, inner.next().unwrap()));
+        let op = inner.next().unwrap(); // to
+        let unit_pair = inner.next().unwrap();
+        let name = unit_name(&unit_pair);
+        if let Some(base) = Base::from_name(&name) {
+            return locate(value.in_base(base), &op);
+        }
+        let target = try_object!(unit(state, unit_pair));
+
+        let result = match (Quantity::from_object(value), target) {
+            (Some(value), Object::Quantity(target)) => value.convert(target, &name),
+            (Some(_), _) => Object::Error(CalcError::units(format!("{} isn't a unit", name))),
+            (None, _) => Object::Error(CalcError::unsupported()),
+        };
+        locate(result, &op)
+    }
+
+    /// Evaluates the unit after a number or `to`. If every name in it is a
+    /// unit, the result remembers how it was written so it can be shown that
+    /// way.
+    fn unit(state: &mut State, pair: Pair<Rule>) -> Object {
+        let name = unit_name(&pair);
+        let mut all_units = true;
+        let mut result: Option<Object> = None;
+        let mut op: Option<Pair<Rule>> = None;
+
+        for pair in pair.into_inner() {
+            if pair.as_rule() != Rule::unit_power {
+                op = Some(pair);
+                continue;
+            }
+
+            let mut inner = pair.into_inner();
+            let symbol = inner.next().unwrap();
+            let base = match state.units.get(symbol.as_str()) {
+                Some(unit) => Object::Quantity(unit.clone()),
+                None => {
+                    all_units = false;
+                    match state.assignments.get(symbol.as_str()) {
+                        Some(value) => value.clone(),
+                        None => {
+                            return Object::Error(CalcError::UnknownUnit {
+                                name: symbol.as_str().to_string(),
+                                span: Some(span_of(&symbol)),
+                            })
+                        }
+                    }
+                }
+            };
+            let power = match inner.nth(1) {
+                Some(exponent) => {
+                    let exponent = try_object!(locate(parse_int(exponent.as_str()), &exponent));
+                    try_object!(locate(base.pow(exponent), &symbol))
+                }
+                None => base,
+            };
+
+            result = Some(match (result, op.take()) {
+                (Some(lhs), Some(op)) => {
+                    let combined = match op.as_rule() {
+                        Rule::mul => lhs * power,
+                        _ => lhs / power,
+                    };
+                    try_object!(locate(combined, &op))
+                }
+                _ => power,
+            });
+        }
+
+        match result.unwrap() {
+            Object::Quantity(quantity) if all_units => {
+                let scale = quantity.value.clone();
+                Object::Quantity(Quantity {
+                    unit: Some(Unit {
+                        name,
+                        scale,
+                    }),
+                    ..quantity
+                })
+            }
+            result => result,
+        }
+    }
+
+    fn call(state: &mut State, name: &str, function: &Function, args: Vec<Object>) -> Object {
+        if args.len() != function.params.len() {
+            let expected = Arity::Exactly(function.params.len());
+            return builtins::wrong_arity(name, expected, args.len());
+        }
+
+        if state.depth >= MAX_DEPTH {
+            return Object::Error(CalcError::overflow(format!("too much recursion in {}", name)));
+        }
+
+        let body = match parse_body(name, &function.body) {
+            Ok(body) => body,
+            Err(error) => return Object::Error(error),
+        };
+        let bindings = function.params.iter().cloned().zip(args).collect();
+
+        state.depth += 1;
+        let result = state.with_bindings(bindings, |state| consume(state, body));
+        state.depth -= 1;
+        result
+    }
+
+    let mut results = Vec::new();
+    let mut pairs = pairs.peekable();
+    while let Some(pair) = pairs.next() {
+        let result = consume(state, pair);
+        if let Object::Error(_) = result {
+            results.push(result);
+            return results;
+        }
+        // The front end sets `ans` to the last result itself.
+        if pairs.peek().is_some() {
+            state.set_ans(&result);
+        }
+        results.push(result);
+    }
+
+    // A line that is only a comment has nothing to show.
+    if results.is_empty() {
+        results.push(Object::Nil);
+    }
+    results
+}
+
+/// The error for using `i` as a variable or parameter name, since it would
+/// always be read back as the imaginary unit.
+fn imaginary_unit(symbol: &Pair<Rule>) -> Option<Object> {
+    if symbol.as_str() != "i" {
+        return None;
+    }
+
+    Some(Object::Error(CalcError::Definition {
+        message: "i is the imaginary unit, so it can't be used as a name".to_string(),
+        span: Some(span_of(symbol)),
+    }))
+}
+
+/// The name of the variable an argument is made of, like the `x` in
+/// `diff(x^2, x)`, or `None` if it is anything more than a name.
+fn variable_name(pair: &Pair<Rule>) -> Option<String> {
+    let mut inner = pair.clone().into_inner();
+    match (inner.next(), inner.next()) {
+        (Some(ref symbol), None) if symbol.as_rule() == Rule::symbol => {
+            Some(symbol.as_str().to_string())
+        }
+        _ => None,
+    }
+}
+
+/// The value of one side of an equation minus the other as a real number
+/// for the root finders, or `None` where there isn't one, like `ln(x)` for
+/// negative `x`. Errors that won't go away with another value of the unknown,
+/// like an unknown variable, are passed on.
+fn real_value(object: Object, name: &str) -> Result<Option<f64>, CalcError> {
+    let object = match object {
+        Object::Quantity(quantity) => *quantity.value,
+        object => object,
+    };
+    match object {
+        Object::Error(CalcError::Domain { .. })
+        | Object::Error(CalcError::DivisionByZero { .. }) => Ok(None),
+        Object::Error(error) => Err(error),
+        Object::Complex(complex) => Ok(if complex.im == 0.0 { Some(complex.re) } else { None }),
+        object => match object.as_float() {
+            Some(value) if value.is_finite() => Ok(Some(value)),
+            Some(_) => Ok(None),
+            None => Err(CalcError::type_mismatch(format!("{} only works with numbers", name))),
+        },
+    }
+}
+
+/// Finds a root of `f` between `a` and `b`, which have to be on either side
+/// of it.
+fn root_between<F>(f: &mut F, a: f64, b: f64) -> Result<Option<f64>, CalcError>
+where
+    F: FnMut(f64) -> Result<Option<f64>, CalcError>,
+{
+    let mut end = |x: f64| match f(x)? {
+        Some(y) => Ok(Sample { x, y }),
+        None => Err(CalcError::domain(format!("there's no real value at {}", Object::Float(x)))),
+    };
+    let (a, b) = (end(a)?, end(b)?);
+    if a.y == 0.0 || b.y == 0.0 {
+        return Ok(Some(if a.y == 0.0 { a.x } else { b.x }));
+    }
+    if a.y.signum() == b.y.signum() {
+        return Err(CalcError::domain(format!(
+            "the values at {} and {} have the same sign, so there's no root between them to find",
+            Object::Float(a.x),
+            Object::Float(b.x)
+        )));
+    }
+    numeric::brent(f, a, b)
+}
+
+/// The items of a list an argument is made of, like the two expressions in
+/// `plot([sin(x), cos(x)], x, 0, 1)`, or `None` if it is anything else.
+fn list_items<'a>(pair: &Pair<'a, Rule>) -> Option<Vec<Pair<'a, Rule>>> {
+    let mut inner = pair.clone().into_inner();
+    match (inner.next(), inner.next()) {
+        (Some(list), None) if list.as_rule() == Rule::list => Some(list.into_inner().collect()),
+        _ => None,
+    }
+}
+
+/// The error for asking `diff` to work with something that isn't
+/// arithmetic, like a comparison or a list.
+fn not_symbolic(pair: &Pair<Rule>) -> CalcError {
+    CalcError::type_mismatch("diff only works with numbers, variables, arithmetic and functions")
+        .at(span_of(pair))
+}
+
+/// How a unit is shown, which is how it was written without any spaces or
+/// comments.
+fn unit_name(pair: &Pair<Rule>) -> String {
+    pair.clone()
+        .into_inner()
+        .flatten()
+        .filter(|pair| pair.as_rule() != Rule::unit_power)
+        .map(|pair| pair.as_str())
+        .collect()
+}
+
+fn span_of(pair: &Pair<Rule>) -> Span {
+    // Rules that end in something optional can take trailing whitespace and
+    // comments with them, which shouldn't be underlined.
+    let span = pair.clone().into_span();
+    if let Some(last) = pair.clone().into_inner().last() {
+        let end = span_of(&last).end;
+        let rest = &pair.as_str()[end - span.start()..];
+        if CalcParser::parse(Rule::blank, rest).is_ok() {
+            return Span::new(span.start(), end);
+        }
+    }
+    Span::new(span.start(), span.start() + pair.as_str().trim_end().len())
+}
+
+/// Points an error that doesn't know where it came from at `pair`.
+fn locate(object: Object, pair: &Pair<Rule>) -> Object {
+    match object {
+        Object::Error(error) => Object::Error(error.or_at(span_of(pair))),
+        object => object,
+    }
+}
+
+/// Parses the body of the function `name`. It was checked when the function
+/// was defined, but it could since have been loaded from a damaged save.
+fn parse_body<'a>(name: &str, body: &'a str) -> Result<Pair<'a, Rule>, CalcError> {
+    match CalcParser::parse(Rule::body, body) {
+        Ok(mut pairs) => Ok(pairs.next().unwrap()),
+        Err(error) => Err(CalcError::Definition {
+            message: format!("the body of {} can't be read: {}", name, parse_error(&error)),
+            span: None,
+        }),
+    }
+}
+
+/// Turns pest's error into one that says what was expected at the point the
+/// input stopped making sense.
+fn parse_error(error: &pest::Error<Rule>) -> CalcError {
+    let (positives, pos) = match *error {
+        pest::Error::ParsingError {
+            ref positives,
+            ref pos,
+            ..
+        } => (positives.clone(), pos.pos()),
+        pest::Error::CustomErrorPos { ref pos, .. } => (Vec::new(), pos.pos()),
+        pest::Error::CustomErrorSpan { ref span, .. } => (Vec::new(), span.start()),
+    };
+
+    let mut expected: Vec<&str> = Vec::new();
+    for rule in positives {
+        let description = match rule {
+            Rule::int
+            | Rule::float
+            | Rule::imaginary
+            | Rule::expr
+            | Rule::neg
+            | Rule::pos
+            | Rule::bit_not
+            | Rule::logical_not
+            | Rule::not
+            | Rule::relation
+            | Rule::boolean
+            | Rule::conditional
+            | Rule::power => "a number",
+            Rule::symbol | Rule::call => "a variable",
+            Rule::unit | Rule::unit_power | Rule::unit_name => "a unit",
+            Rule::add
+            | Rule::sub
+            | Rule::mul
+            | Rule::div
+            | Rule::rem
+            | Rule::pow
+            | Rule::shl
+            | Rule::shr
+            | Rule::bit_and
+            | Rule::bit_or
+            | Rule::bit_xor
+            | Rule::eq
+            | Rule::ne
+            | Rule::lt
+            | Rule::le
+            | Rule::gt
+            | Rule::ge
+            | Rule::and
+            | Rule::or => "an operator",
+            Rule::then => "`then`",
+            Rule::else_ => "`else`",
+            _ => continue,
+        };
+        if !expected.contains(&description) {
+            expected.push(description);
+        }
+    }
+
+    let message = match expected.split_last() {
+        None => "this doesn't make sense here".to_string(),
+        Some((last, [])) => format!("expected {}", last),
+        Some((last, rest)) => format!("expected {} or {}", rest.join(", "), last),
+    };
+
+    CalcError::Parse {
+        message,
+        span: Some(Span::new(pos, pos + 1)),
+    }
+}
+
+/// Parses an integer literal such as `-143` or `50E2`, which can be any
+/// length. A negative order of magnitude, like in `5E-1`, gives a rational.
+fn parse_int(literal: &str) -> Object {
+    let literal = &literal.replace('_', "");
+    for &(prefix, radix) in &[("0x", 16), ("0o", 8), ("0b", 2)] {
+        if let Some(digits) = literal.strip_prefix(prefix) {
+            return BigInt::parse_bytes(digits.as_bytes(), radix).unwrap().into();
+        }
+    }
+
+    let mut parts = literal.splitn(2, ['e', 'E']);
+    let mantissa = parts.next().unwrap().parse::<BigInt>().unwrap();
+
+    match parts.next() {
+        Some(exp) => match exp.parse::<i64>() {
+            Ok(exp) => match Object::Integer(10).pow(Object::Integer(exp)) {
+                error @ Object::Error(_) => error,
+                scale => Object::from(mantissa) * scale,
+            },
+            Err(_) => Object::Error(CalcError::overflow(format!(
+                "the exponent in {} is too large",
+                literal
+            ))),
+        },
+        None => mantissa.into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use num_rational::BigRational;
+
+    fn eval(input: &str) -> Object {
+        evaluate(&mut State::new(), input)
+    }
+
+    fn ratio(numer: i64, denom: i64) -> Object {
+        Object::Rational(BigRational::new(numer.into(), denom.into()))
+    }
+
+    fn big(literal: &str) -> Object {
+        Object::BigInteger(literal.parse().unwrap())
+    }
+
+    fn message(object: Object) -> String {
+        match object {
+            Object::Error(error) => error.to_string(),
+            other => panic!("expected an error but got {:?}", other),
+        }
+    }
+
+    /// The underline for the error from evaluating `input`.
+    fn underline(input: &str) -> String {
+        match eval(input) {
+            Object::Error(error) => error.underline(input).unwrap(),
+            other => panic!("expected an error but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(eval("1 + 2 * 3"), Object::Integer(7));
+        assert_eq!(eval("2 ^ 3 ^ 2"), Object::Integer(512));
+        assert_eq!(eval("0.5 * 4"), Object::Float(2.0));
+    }
+
+    #[test]
+    fn signs() {
+        assert_eq!(eval("-2^2"), Object::Integer(-4));
+        assert_eq!(eval("(-2)^2"), Object::Integer(4));
+        assert_eq!(eval("2^-1"), ratio(1, 2));
+        assert_eq!(eval("-(1 + 2)"), Object::Integer(-3));
+        assert_eq!(eval("3 -2"), Object::Integer(1));
+        assert_eq!(eval("3 - -2"), Object::Integer(5));
+        assert_eq!(eval("-2 * 3"), Object::Integer(-6));
+        assert_eq!(eval("+5 - -0.5"), Object::Float(5.5));
+        assert_eq!(eval("--9223372036854775808"), big("9223372036854775808"));
+
+        let mut state = State::new();
+        evaluate(&mut state, "x = 4");
+        assert_eq!(evaluate(&mut state, "-x"), Object::Integer(-4));
+    }
+
+    #[test]
+    fn rationals() {
+        assert_eq!(eval("1/3 * 3"), Object::Integer(1));
+        assert_eq!(eval("6/4"), ratio(3, 2));
+        assert_eq!(eval("1/2 + 1/3"), ratio(5, 6));
+        assert_eq!(eval("2^-2"), ratio(1, 4));
+        assert_eq!(eval("(2/3)^2"), ratio(4, 9));
+        assert_eq!(eval("(7/2) % 1"), ratio(1, 2));
+        assert_eq!(eval("1/4 + 0.5"), Object::Float(0.75));
+        assert_eq!(eval("(1/4)^0.5"), Object::Float(0.5));
+    }
+
+    #[test]
+    fn big_integers() {
+        assert_eq!(eval("2^70"), big("1180591620717411303424"));
+        assert_eq!(eval("9223372036854775807 + 1"), big("9223372036854775808"));
+        assert_eq!(eval("2^70 - 2^70 + 5"), Object::Integer(5));
+        assert_eq!(eval("2^64 / 2^62"), Object::Integer(4));
+        assert_eq!(eval("1/2^64"), Object::Rational(BigRational::new(
+            1.into(),
+            "18446744073709551616".parse().unwrap(),
+        )));
+        assert_eq!(
+            eval("123456789012345678901234567890 * 10"),
+            big("1234567890123456789012345678900")
+        );
+        assert_eq!(eval("50E2"), Object::Integer(5000));
+        assert_eq!(eval("5E-1"), ratio(1, 2));
+    }
+
+    #[test]
+    fn builtins() {
+        assert_eq!(eval("sqrt(16)"), Object::Integer(4));
+        assert_eq!(eval("sqrt(4/9)"), ratio(2, 3));
+        assert_eq!(eval("sqrt(2)"), Object::Float(2f64.sqrt()));
+        assert_eq!(eval("abs(-3/4)"), ratio(3, 4));
+        assert_eq!(eval("cos(0)"), Object::Float(1.0));
+        assert_eq!(eval("log(8, 2)"), Object::Float(3.0));
+        assert_eq!(eval("floor(-7/2)"), Object::Integer(-4));
+        assert_eq!(eval("round(2.5)"), Object::Float(3.0));
+        assert_eq!(eval("max(1, 5/2, 2.0)"), ratio(5, 2));
+        assert_eq!(eval("min(3, -1, 2) * 2"), Object::Integer(-2));
+        assert_eq!(eval("gcd(12, 18, 8)"), Object::Integer(2));
+        assert_eq!(eval("lcm(4, 6)"), Object::Integer(12));
+        assert_eq!(eval("sqrt(abs(-16)) + 1"), Object::Integer(5));
+    }
+
+    #[test]
+    fn complex_numbers() {
+        let complex = |re, im| Object::Complex(Complex64::new(re, im));
+        assert_eq!(eval("3 + 4i"), complex(3.0, 4.0));
+        assert_eq!(eval("i * i"), Object::Float(-1.0));
+        assert_eq!(eval("(1 + 2i) * (3 - i)"), complex(5.0, 5.0));
+        assert_eq!(eval("(1 + i) / (1 - i)"), complex(0.0, 1.0));
+        assert_eq!(eval("-2i"), complex(0.0, -2.0));
+        assert_eq!(eval("(1 + i)^2"), complex(0.0, 2.0));
+        assert_eq!(eval("i^-1"), complex(0.0, -1.0));
+        assert_eq!(eval("sqrt(-1)"), complex(0.0, 1.0));
+        assert_eq!(eval("sqrt(-4) + 1"), complex(1.0, 2.0));
+        assert_eq!(eval("abs(3 + 4i)"), Object::Float(5.0));
+        assert_eq!(eval("exp(0i)"), Object::Float(1.0));
+        assert_eq!(eval("2e3i"), complex(0.0, 2000.0));
+        assert_eq!(eval("1_000i"), complex(0.0, 1000.0));
+        assert_eq!(eval("0x1Fi"), complex(0.0, 31.0));
+        assert_eq!(eval("0b1i"), complex(0.0, 1.0));
+        assert_eq!(message(eval("1e400i")), "that number is too large");
+
+        match eval("(-8)^(1/3)") {
+            Object::Complex(root) => {
+                assert!((root.re - 1.0).abs() < 1e-12);
+                assert!((root.im - 3f64.sqrt()).abs() < 1e-12);
+            }
+            other => panic!("expected a complex number but got {:?}", other),
+        }
+
+        assert_eq!(eval("3 - 4i").to_string(), "3-4i");
+        assert_eq!(eval("2.5i").to_string(), "2.5i");
+
+        assert_eq!(message(eval("1/(0i)")), "division by zero");
+        assert_eq!(message(eval("i % 2")), "remainders aren't defined for complex numbers");
+        assert_eq!(message(eval("floor(i)")), "floor can only be applied to real numbers");
+        assert_eq!(message(eval("max(1, i)")), "max can only be applied to real numbers");
+        assert_eq!(message(eval("i = 2")), "i is the imaginary unit, so it can't be used as a name");
+        assert_eq!(message(eval("f(i) = i")), "i is the imaginary unit, so it can't be used as a name");
+
+        let mut state = State::new();
+        evaluate(&mut state, "z = 50 + 20i");
+        evaluate(&mut state, "in = 2");
+        assert_eq!(evaluate(&mut state, "z * in"), complex(100.0, 40.0));
+    }
+
+    #[test]
+    fn units() {
+        let show = |input| eval(input).to_string();
+        assert_eq!(show("3 m"), "3 m");
+        assert_eq!(show("12 kg*m/s^2"), "12 kg*m/s^2");
+        assert_eq!(show("60 mph"), "60 mph");
+        assert_eq!(show("3 km + 500 m"), "3.5 km");
+        assert_eq!(show("2 * 3 km"), "6 km");
+        assert_eq!(show("2 kg * 3 m/s^2"), "6 kg*m/s^2");
+        assert_eq!(show("100 km / 2 h to km/h"), "50 km/h");
+        assert_eq!(show("1 ft to in"), "12 in");
+        assert_eq!(show("1 GiB to MB"), "1073.741824 MB");
+        assert_eq!(show("60 mph to km/h"), "96.56064 km/h");
+        assert_eq!(show("sqrt(16 m^2)"), "4 m");
+        assert_eq!(show("(50 + 20i) * 1 ohm * 2 A"), "100+40i kg*m^2/(s^3*A)");
+        assert_eq!(show("max(1 m, 2 ft)"), "1 m");
+        assert_eq!(eval("5 km / m"), Object::Integer(5000));
+        assert_eq!(eval("1 L / (10 cm)^3"), Object::Integer(1));
+        assert_eq!(show("1 kWh to J"), "3600000 J");
+        assert_eq!(show("1 atm to kPa"), "101.325 kPa");
+        assert_eq!(show("1 MeV to J"), "0.0000000000001602176634 J");
+        assert_eq!(show("1 KiB to byte"), "1024 byte");
+        assert_eq!(show("1 / 2 m"), "0.5 m^-1");
+        assert_eq!(show("1 / (2 m * 1 s^2)"), "0.5 m^-1*s^-2");
+        assert_eq!(show("4 m/s / 2 m"), "2 s^-1");
+
+        // Names after a number are units first, but anywhere else variables
+        // come first.
+        let mut state = State::new();
+        evaluate(&mut state, "s = 4");
+        evaluate(&mut state, "x = 2");
+        assert_eq!(evaluate(&mut state, "s * 2"), Object::Integer(8));
+        assert_eq!(evaluate(&mut state, "1 s to ms").to_string(), "1000 ms");
+        assert_eq!(evaluate(&mut state, "3 x"), Object::Integer(6));
+        evaluate(&mut state, "d = 5 km to mi");
+        assert_eq!(evaluate(&mut state, "d * 2").to_string(), "6.2137119223733395 mi");
+        // Even a variable that isn't a number is passed over for a unit.
+        evaluate(&mut state, "A = [[1, 2], [3, 4]]");
+        assert_eq!(evaluate(&mut state, "2 A").to_string(), "2 A");
+        assert_eq!(evaluate(&mut state, "2 * A").to_string(), "[[2, 4], [6, 8]]");
+
+        assert_eq!(message(eval("1 m + 1 s")), "can't add m and s");
+        assert_eq!(underline("1 m + 1 s"), "    ^");
+        assert_eq!(message(eval("1 m - 2")), "can't subtract m and a number without units");
+        assert_eq!(message(eval("1 m to s")), "can't convert m to s");
+        assert_eq!(message(eval("5 to mi")), "can't convert a number without units to mi");
+        assert_eq!(message(eval("2^(1 m)")), "exponents can't have units");
+        assert_eq!(message(eval("sqrt(4 m)")), "m can't be raised to that power");
+        assert_eq!(message(eval("3 furlong")), "no unit or variable named furlong");
+        assert_eq!(underline("3 furlong"), "  ^^^^^^^");
+    }
+
+    #[test]
+    fn bases() {
+        assert_eq!(eval("0x1F"), Object::Integer(31));
+        assert_eq!(eval("0o17"), Object::Integer(15));
+        assert_eq!(eval("0b1011"), Object::Integer(11));
+        assert_eq!(eval("1_000_000"), Object::Integer(1_000_000));
+        assert_eq!(eval("0xFFFF_FFFF_FFFF_FFFF"), big("18446744073709551615"));
+        assert_eq!(eval("0xff"), Object::Integer(255));
+        assert_eq!(eval("0x1Fi + 0b1i - 1_000i").to_string(), "-968i");
+
+        assert_eq!(eval("255 to hex").to_string(), "0xFF");
+        assert_eq!(eval("-10 to bin").to_string(), "-0b1010");
+        assert_eq!(eval("8 to oct").to_string(), "0o10");
+        assert_eq!(eval("0x10 to dec"), Object::Integer(16));
+        assert_eq!(eval("2^70 to hex").to_string(), "0x400000000000000000");
+
+        // Shown in a base, the value still works like any other integer.
+        let mut state = State::new();
+        evaluate(&mut state, "mask = 0xF0 to bin");
+        assert_eq!(evaluate(&mut state, "mask").to_string(), "0b11110000");
+        assert_eq!(evaluate(&mut state, "mask + 1"), Object::Integer(241));
+        assert_eq!(evaluate(&mut state, "mask / 32"), ratio(15, 2));
+
+        assert_eq!(message(eval("1/2 to hex")), "only integers can be shown in another base");
+        assert_eq!(underline("1.5 to hex"), "    ^^");
+    }
+
+    #[test]
+    fn bitwise() {
+        assert_eq!(eval("0xF0 & 0x3C"), Object::Integer(0x30));
+        assert_eq!(eval("5 | 2"), Object::Integer(7));
+        assert_eq!(eval("6 xor 3"), Object::Integer(5));
+        assert_eq!(eval("~0"), Object::Integer(-1));
+        assert_eq!(eval("1 << 70"), big("1180591620717411303424"));
+        assert_eq!(eval("-8 >> 1"), Object::Integer(-4));
+        assert_eq!(eval("1 | 2 & 3"), Object::Integer(3));
+        assert_eq!(eval("1 + 1 << 2"), Object::Integer(8));
+
+        // A result keeps the base of the number it came from.
+        let mut state = State::new();
+        evaluate(&mut state, "mask = 0xF0 to hex");
+        assert_eq!(evaluate(&mut state, "mask | 1").to_string(), "0xF1");
+        evaluate(&mut state, "x = 12");
+        evaluate(&mut state, "y = 10");
+        assert_eq!(evaluate(&mut state, "x xor y"), Object::Integer(6));
+
+        assert_eq!(eval("popcount(0xFF)"), Object::Integer(8));
+        assert_eq!(eval("bit(5, 0)"), Object::Integer(1));
+        assert_eq!(eval("bit(5, 1)"), Object::Integer(0));
+
+        assert_eq!(message(eval("1.5 & 1")), "bitwise operators can only be applied to integers");
+        assert_eq!(underline("1.5 & 1"), "    ^");
+        assert_eq!(message(eval("1 << -1")), "can't shift by a negative amount");
+        assert_eq!(message(eval("1 << 10000000")), "that shift is too large");
+        assert_eq!(message(eval("popcount(-1)")), "popcount isn't defined for that number");
+    }
+
+    #[test]
+    fn builtin_errors() {
+        assert_eq!(message(eval("sqrt(1, 2)")), "sqrt takes 1 argument but 2 were given");
+        assert_eq!(message(eval("gcd(1)")), "gcd takes at least 2 arguments but 1 was given");
+        assert_eq!(message(eval("min()")), "min takes at least 1 argument but 0 were given");
+        assert_eq!(message(eval("gcd(1.5, 3)")), "gcd can only be applied to integers");
+        assert_eq!(message(eval("nope(1)")), "no function named nope");
+        assert_eq!(message(eval("sin(nope)")), "no variable named nope");
+    }
+
+    #[test]
+    fn user_functions() {
+        let mut state = State::new();
+        assert_eq!(evaluate(&mut state, "f(x, y) = x^2 + y"), Object::Nil);
+        assert_eq!(evaluate(&mut state, "f(3, 4)"), Object::Integer(13));
+
+        // Parameters shadow variables, but only during the call.
+        evaluate(&mut state, "x = 10");
+        assert_eq!(evaluate(&mut state, "f(2, x)"), Object::Integer(14));
+        assert_eq!(evaluate(&mut state, "x"), Object::Integer(10));
+
+        // Other variables are looked up when the function is called.
+        evaluate(&mut state, "g(t) = t * rate");
+        evaluate(&mut state, "rate = 3");
+        assert_eq!(evaluate(&mut state, "g(f(1, 1))"), Object::Integer(6));
+
+        evaluate(&mut state, "k() = 7");
+        assert_eq!(evaluate(&mut state, "k() + 1"), Object::Integer(8));
+    }
+
+    #[test]
+    fn user_function_errors() {
+        let mut state = State::new();
+        evaluate(&mut state, "f(x) = x");
+        assert_eq!(message(evaluate(&mut state, "f(1, 2)")), "f takes 1 argument but 2 were given");
+        assert_eq!(message(evaluate(&mut state, "g(x, x) = x")), "x is used as a parameter more than once");
+        assert_eq!(message(evaluate(&mut state, "sqrt(x) = x")), "sqrt is a built in function");
+
+        evaluate(&mut state, "r(x) = r(x + 1)");
+        assert_eq!(message(evaluate(&mut state, "r(0)")), "too much recursion in r");
+        assert_eq!(evaluate(&mut state, "r(0)"), evaluate(&mut state, "r(1)"));
+
+        // A body that was loaded rather than typed in might not parse.
+        for &(name, body) in &[("bad", "[| x |]"), ("trailing", "x |> sqrt")] {
+            let function = Function {
+                params: vec!["x".to_string()],
+                body: body.to_string(),
+            };
+            state.functions.insert(name.to_string(), function);
+        }
+        assert_eq!(
+            message(evaluate(&mut state, "bad(1)")),
+            "the body of bad can't be read: expected a number"
+        );
+        match evaluate(&mut state, "bad(1)") {
+            Object::Error(error) => assert_eq!(error.underline("bad(1)").unwrap(), "^^^^^^"),
+            other => panic!("expected an error, got {}", other),
+        }
+        assert_eq!(
+            message(evaluate(&mut state, "trailing(4)")),
+            "the body of trailing can't be read: expected a number or a variable"
+        );
+        assert_eq!(
+            message(evaluate(&mut state, "diff(trailing(x), x)")),
+            "the body of trailing can't be read: expected a number or a variable"
+        );
+    }
+
+    #[test]
+    fn assignment() {
+        let mut state = State::new();
+        assert_eq!(evaluate(&mut state, "a = 4"), Object::Nil);
+        assert_eq!(evaluate(&mut state, "a * 2.5"), Object::Float(10.0));
+    }
+
+    #[test]
+    fn unknown_variable() {
+        assert_eq!(message(eval("nope")), "no variable named nope");
+    }
+
+    #[test]
+    fn parse_error() {
+        assert_eq!(message(eval("3 + * 4")), "expected a number or a variable");
+        assert_eq!(underline("3 + * 4"), "    ^");
+        assert_eq!(message(eval("1 2")), "expected a unit or an operator");
+        assert_eq!(message(eval("(1) 2")), "expected an operator");
+        assert_eq!(underline("1 2"), "  ^");
+    }
+
+    #[test]
+    fn arithmetic_safety() {
+        assert_eq!(message(eval("1/0")), "division by zero");
+        assert_eq!(underline("1 / 0"), "  ^");
+        assert_eq!(message(eval("1 % 0")), "division by zero");
+        assert_eq!(message(eval("(1/2) / 0")), "division by zero");
+        assert_eq!(message(eval("1.5 / 0.0")), "division by zero");
+        assert_eq!(message(eval("2.5 % 0")), "division by zero");
+        assert_eq!(message(eval("3/0 + 1")), "division by zero");
+        assert_eq!(message(eval("0^-1")), "division by zero");
+
+        assert_eq!(message(eval("2^10000000")), "that number is too large to work out exactly");
+        assert_eq!(message(eval("2^-10000000")), "that number is too large to work out exactly");
+        assert_eq!(eval("1^10000000000"), Object::Integer(1));
+        assert_eq!(eval("(0-1)^10000000001"), Object::Integer(-1));
+        assert_eq!(eval("0^10000000000"), Object::Integer(0));
+        assert_eq!(message(eval("1.5e400")), "that number is too large");
+        assert_eq!(message(eval("5E99999999999")), "that number is too large to work out exactly");
+        assert_eq!(
+            message(eval("5E99999999999999999999")),
+            "the exponent in 5E99999999999999999999 is too large"
+        );
+        assert_eq!(message(eval("exp(1000)")), "that number is too large");
+        assert_eq!(message(eval("1.5 ^ 100000")), "that number is too large");
+        assert_eq!(eval("(0-9223372036854775807-1) % (0-1)"), Object::Integer(0));
+    }
+
+    #[test]
+    fn error_spans() {
+        assert_eq!(underline("1 + nope * 2"), "    ^^^^");
+        assert_eq!(underline("2 * (1 + help())"), "         ^^^^");
+        assert_eq!(underline("1 + bad(2)"), "    ^^^");
+        assert_eq!(underline("2 + sqrt(1, 2)"), "    ^^^^");
+        assert_eq!(message(eval("ln(0)")), "ln isn't defined for that number");
+        assert_eq!(underline("ln(0)"), "^^");
+
+        // Errors inside a user defined function point at the call.
+        let mut state = State::new();
+        evaluate(&mut state, "f(x) = x + missing");
+        let input = "1 + f(2)";
+        match evaluate(&mut state, input) {
+            Object::Error(error) => {
+                assert_eq!(error.to_string(), "no variable named missing");
+                assert_eq!(error.underline(input).unwrap(), "    ^^^^");
+            }
+            other => panic!("expected an error but got {:?}", other),
+        }
+
+        // Errors aren't stored by assignments.
+        assert_eq!(message(evaluate(&mut state, "a = missing")), "no variable named missing");
+        assert_eq!(message(evaluate(&mut state, "a")), "no variable named a");
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(eval("1 < 2"), Object::Boolean(true));
+        assert_eq!(eval("2 <= 2"), Object::Boolean(true));
+        assert_eq!(eval("3 > 4"), Object::Boolean(false));
+        assert_eq!(eval("3 >= 4"), Object::Boolean(false));
+        assert_eq!(eval("2 != 2"), Object::Boolean(false));
+        assert_eq!(eval("1/2 == 0.5"), Object::Boolean(true));
+        assert_eq!(eval("2^70 > 2^69"), Object::Boolean(true));
+        assert_eq!(eval("1 << 2 < 5"), Object::Boolean(true));
+        assert_eq!(eval("true").to_string(), "true");
+
+        // Floats only have to be within rounding error of each other.
+        assert_eq!(eval("0.1 + 0.2 == 0.3"), Object::Boolean(true));
+        assert_eq!(eval("0.1 + 0.2 <= 0.3"), Object::Boolean(true));
+        assert_eq!(eval("0.1 + 0.2 > 0.3"), Object::Boolean(false));
+        assert_eq!(eval("sin(pi) == 0"), Object::Boolean(true));
+        assert_eq!(eval("1.000001 == 1"), Object::Boolean(false));
+        // The tolerance is relative, so tiny values are still told apart.
+        assert_eq!(eval("1.5 pF < 2.5 pF"), Object::Boolean(true));
+        assert_eq!(eval("1.5 pF == 1.5000000001 pF"), Object::Boolean(true));
+        assert_eq!(eval("1.5e-15 < 2.5e-15"), Object::Boolean(true));
+        assert_eq!(eval("2.5e-15 >= 1.5e-15"), Object::Boolean(true));
+        assert_eq!(eval("1.6e-19 == 9.1e-31"), Object::Boolean(false));
+        assert_eq!(eval("if 1.0e-13 > 0 then 1 else 0"), Object::Integer(1));
+        assert_eq!(eval("-1.0e-13 < 0"), Object::Boolean(true));
+        assert_eq!(eval("(1 + 2i) * (1 - 2i) == 5"), Object::Boolean(true));
+        assert_eq!(eval("i != 1"), Object::Boolean(true));
+
+        // Quantities are compared in base units.
+        assert_eq!(eval("3 km > 1 mi"), Object::Boolean(true));
+        assert_eq!(eval("1 in == 2.54 cm"), Object::Boolean(true));
+
+        let mut state = State::new();
+        evaluate(&mut state, "ok = 9.81 m/s^2 < 10 m/s^2");
+        assert_eq!(evaluate(&mut state, "ok"), Object::Boolean(true));
+
+        assert_eq!(message(eval("1 km < 1 s")), "can't compare m and s");
+        assert_eq!(underline("1 km < 1 s"), "     ^");
+        assert_eq!(message(eval("i < 1")), "complex numbers can't be ordered");
+        assert_eq!(message(eval("true == 1")), "true and false can't be compared with numbers");
+        assert_eq!(message(eval("true < false")), "true and false can only be compared with == and !=");
+        assert_eq!(message(eval("true + 1")), "that operation isn't supported");
+    }
+
+    #[test]
+    fn logic() {
+        assert_eq!(eval("1 < 2 and 2 < 3"), Object::Boolean(true));
+        assert_eq!(eval("1 > 2 or 2 > 3"), Object::Boolean(false));
+        assert_eq!(eval("true or false and false"), Object::Boolean(true));
+        assert_eq!(eval("not true"), Object::Boolean(false));
+        assert_eq!(eval("not not true"), Object::Boolean(true));
+
+        // `not` covers the whole comparison, but stops at `and` and `or`.
+        assert_eq!(eval("not 1 > 2"), Object::Boolean(true));
+        assert_eq!(eval("not 1 < 2 or true"), Object::Boolean(true));
+        assert_eq!(eval("true and not false"), Object::Boolean(true));
+
+        let mut state = State::new();
+        evaluate(&mut state, "x = 0.3");
+        assert_eq!(evaluate(&mut state, "x > 0.1 and x < 0.5"), Object::Boolean(true));
+        evaluate(&mut state, "within(v, lo, hi) = v >= lo and v <= hi");
+        assert_eq!(evaluate(&mut state, "within(5 V, 4.5 V, 5.5 V)"), Object::Boolean(true));
+
+        assert_eq!(message(eval("1 and true")), "and can only be applied to true or false");
+        assert_eq!(underline("1 and true"), "  ^^^");
+        assert_eq!(message(eval("not 1")), "not can only be applied to true or false");
+        assert_eq!(message(eval("5 and")), "expected a number or a variable");
+        assert_eq!(message(eval("5 true")), "expected a unit or an operator");
+    }
+
+    #[test]
+    fn conditionals() {
+        assert_eq!(eval("if 1 < 2 then 10 else 20"), Object::Integer(10));
+        assert_eq!(eval("if false then 10 else 20"), Object::Integer(20));
+        assert_eq!(eval("if 3 then 1 else 0"), Object::Integer(1));
+        assert_eq!(eval("if 0.0 then 1 else 0"), Object::Integer(0));
+        assert_eq!(eval("if i then 1 else 0"), Object::Integer(1));
+        assert_eq!(eval("2 * if true then 3 else 4"), Object::Integer(6));
+        assert_eq!(eval("if false then 1 else 2 + 3"), Object::Integer(5));
+        assert_eq!(eval("(if true then 1 else 2) + 3"), Object::Integer(4));
+        assert_eq!(eval("if true then if false then 1 else 2 else 3"), Object::Integer(2));
+
+        // The branch that isn't taken is never evaluated.
+        assert_eq!(eval("if true then 1 else 1/0"), Object::Integer(1));
+        assert_eq!(eval("if false then nope else 2"), Object::Integer(2));
+
+        // Piecewise functions, including recursive ones.
+        let mut state = State::new();
+        evaluate(&mut state, "tax(x) = if x <= 10000 then 0 else (x - 10000) * 0.2");
+        assert_eq!(evaluate(&mut state, "tax(8000)"), Object::Integer(0));
+        assert_eq!(evaluate(&mut state, "tax(15000)"), Object::Float(1000.0));
+        evaluate(&mut state, "fact(n) = if n <= 1 then 1 else n * fact(n - 1)");
+        assert_eq!(evaluate(&mut state, "fact(25)"), big("15511210043330985984000000"));
+        evaluate(&mut state, "down(n) = if n <= 0 then 0 else down(n - 1)");
+        assert_eq!(evaluate(&mut state, "down(120)"), Object::Integer(0));
+        assert_eq!(message(evaluate(&mut state, "down(1000)")), "too much recursion in down");
+        assert_eq!(evaluate(&mut state, "x = if fact(3) == 6 then 1 else 0"), Object::Nil);
+        assert_eq!(evaluate(&mut state, "x"), Object::Integer(1));
+
+        assert_eq!(
+            message(eval("if 5 m then 1 else 0")),
+            "the condition has to be true, false or a number without units"
+        );
+        assert_eq!(underline("if 5 m then 1 else 0"), "   ^^^");
+        assert_eq!(message(eval("if nope then 1 else 0")), "no variable named nope");
+        assert_eq!(message(eval("if true then 1")), "expected a unit, an operator or `else`");
+    }
+
+    #[test]
+    fn statements() {
+        let mut state = State::new();
+        assert_eq!(evaluate(&mut state, "a = 2; b = 3; a * b"), Object::Integer(6));
+        assert_eq!(evaluate(&mut state, "b"), Object::Integer(3));
+        assert_eq!(evaluate(&mut state, "c = a + b;"), Object::Nil);
+        assert_eq!(evaluate(&mut state, "c"), Object::Integer(5));
+        assert_eq!(evaluate(&mut state, "f(x) = x * 2; f(c)"), Object::Integer(10));
+        assert_eq!(evaluate(&mut state, "2 + 3; ans * 2"), Object::Integer(10));
+
+        assert_eq!(
+            evaluate_each(&mut state, "x = 4; x^2; x to hex; 5 km"),
+            vec![
+                Object::Nil,
+                Object::Integer(16),
+                Object::InBase(4.into(), Base::Hexadecimal),
+                evaluate(&mut state, "5 km"),
+            ]
+        );
+
+        // Statements before an error still happen, but none after it do.
+        let input = "y = 1; y / 0; y = 2";
+        let results = evaluate_each(&mut state, input);
+        assert_eq!(results.len(), 2);
+        assert_eq!(message(results[1].clone()), "division by zero");
+        assert_eq!(evaluate(&mut state, "y"), Object::Integer(1));
+        assert_eq!(underline(input), "         ^");
+
+        assert_eq!(message(eval("1;;2")), "expected a number or a variable");
+        assert_eq!(message(eval(";")), "expected a number or a variable");
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(eval("1 + 2 # three"), Object::Integer(3));
+        assert_eq!(eval("1 + /* two */ 2"), Object::Integer(3));
+        assert_eq!(eval("2 /* m */ * 3 # six"), Object::Integer(6));
+        assert_eq!(eval("# nothing but a note"), Object::Nil);
+        assert_eq!(eval("/* nothing */"), Object::Nil);
+        assert_eq!(eval("5 km # to mi"), eval("5 km"));
+        assert_eq!(eval("3#x"), Object::Integer(3));
+
+        let mut state = State::new();
+        assert_eq!(evaluate(&mut state, "g = 9.81 # at sea level, from the handbook"), Object::Nil);
+        assert_eq!(evaluate(&mut state, "g"), Object::Float(9.81));
+        evaluate(&mut state, "f(x) = x * 2 # doubles x");
+        assert_eq!(evaluate(&mut state, "f(4)"), Object::Integer(8));
+        assert_eq!(evaluate(&mut state, "a = 1; # b = 2; c = 3"), Object::Nil);
+        assert_eq!(message(evaluate(&mut state, "b")), "no variable named b");
+
+        assert_eq!(message(eval("1 + 2 /* unfinished")), "expected a number or a variable");
+        assert_eq!(message(eval("1 + # 2")), "expected a number or a variable");
+        assert_eq!(message(eval("3 furlong # a unit")), "no unit or variable named furlong");
+        assert_eq!(underline("3 furlong # a unit"), "  ^^^^^^^");
+        assert_eq!(underline("if 5 m # length\nthen 1 else 0"), "   ^^^");
+        assert_eq!(underline("-(1.5 & 1) /* oops */"), "      ^");
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            eval("[1, 2, 3]"),
+            Object::List(vec![Object::Integer(1), Object::Integer(2), Object::Integer(3)])
+        );
+        assert_eq!(eval("[]"), Object::List(vec![]));
+        assert_eq!(eval("[1, 2/4, 0.5, [3]]").to_string(), "[1, 1/2, 0.5, [3]]");
+
+        // Arithmetic works on each element, against a single value or the
+        // matching element of another list.
+        assert_eq!(eval("[1, 2, 3] * 2").to_string(), "[2, 4, 6]");
+        assert_eq!(eval("10 - [1, 2]").to_string(), "[9, 8]");
+        assert_eq!(eval("[1, 2] + [10, 20]").to_string(), "[11, 22]");
+        assert_eq!(eval("[1, 2] / 4").to_string(), "[1/4, 1/2]");
+        assert_eq!(eval("[2, 3]^2").to_string(), "[4, 9]");
+        assert_eq!(eval("-[1, -2]").to_string(), "[-1, 2]");
+        assert_eq!(eval("[[1, 2], [3]] * 2").to_string(), "[[2, 4], [6]]");
+        assert_eq!(eval("[1, 2] * 1 km").to_string(), "[1 km, 2 km]");
+
+        let mut state = State::new();
+        evaluate(&mut state, "v = [10, 20, 30]");
+        assert_eq!(evaluate(&mut state, "v[0]"), Object::Integer(10));
+        assert_eq!(evaluate(&mut state, "v[-1]"), Object::Integer(30));
+        assert_eq!(evaluate(&mut state, "v[1 + 1]^2"), Object::Integer(900));
+        assert_eq!(evaluate(&mut state, "[[1, 2], [3, 4]][1][0]"), Object::Integer(3));
+        evaluate(&mut state, "f(x) = [x, x * 2]");
+        assert_eq!(evaluate(&mut state, "f(5)[1]"), Object::Integer(10));
+
+        assert_eq!(eval("sum([1, 2, 3])"), Object::Integer(6));
+        assert_eq!(eval("sum(1, 2, 3)"), Object::Integer(6));
+        assert_eq!(eval("sum([])"), Object::Integer(0));
+        assert_eq!(eval("sum([1 km, 500 m])").to_string(), "1.5 km");
+        assert_eq!(eval("product([2, 3, 4])"), Object::Integer(24));
+        assert_eq!(eval("mean([1, 2])"), ratio(3, 2));
+        assert_eq!(eval("mean([1.5, 2.5])"), Object::Float(2.0));
+        assert_eq!(eval("len([1, [2, 3]])"), Object::Integer(2));
+        assert_eq!(eval("max([3, 9, 4])"), Object::Integer(9));
+        assert_eq!(eval("min([3, 9, 4])"), Object::Integer(3));
+
+        assert_eq!(
+            message(eval("[1, 2] + [1, 2, 3]")),
+            "can't combine a list of 2 with a list of 3"
+        );
+        assert_eq!(underline("[1, 2] + [1, 2, 3]"), "       ^");
+        assert_eq!(message(eval("[1, 1/0]")), "division by zero");
+        assert_eq!(message(eval("[1, 2][2]")), "index 2 is out of range for a list of 2");
+        assert_eq!(underline("[1, 2][2]"), "       ^");
+        assert_eq!(
+            message(eval("[1, 2][2^70]")),
+            "index 1180591620717411303424 is out of range for a list of 2"
+        );
+        assert_eq!(
+            message(eval("[1, 2][-2^70]")),
+            "index -1180591620717411303424 is out of range for a list of 2"
+        );
+        assert_eq!(message(eval("[1, 2][0.5]")), "list indices have to be integers");
+        assert_eq!(message(eval("5[0]")), "only lists and matrices can be indexed");
+        assert_eq!(message(eval("sum([1, true])")), "sum can only be applied to numbers");
+        assert_eq!(message(eval("sum([1 km, 1 s])")), "can't add m and s");
+        assert_eq!(message(eval("mean([])")), "mean isn't defined for an empty list");
+        assert_eq!(message(eval("max([])")), "max isn't defined for an empty list");
+        assert_eq!(message(eval("len(5)")), "len can only be applied to lists");
+    }
+
+    #[test]
+    fn matrices() {
+        let mut state = State::new();
+        evaluate(&mut state, "A = [[1, 2], [3, 4]]");
+        match evaluate(&mut state, "A") {
+            Object::Matrix(_) => {}
+            other => panic!("expected a matrix, got {:?}", other),
+        }
+        assert_eq!(evaluate(&mut state, "A").to_string(), "[[1, 2], [3, 4]]");
+        // Rows that aren't all numbers of the same length stay lists.
+        match eval("[[1, 2], [3]]") {
+            Object::List(_) => {}
+            other => panic!("expected a list, got {:?}", other),
+        }
+
+        assert_eq!(evaluate(&mut state, "A * A").to_string(), "[[7, 10], [15, 22]]");
+        assert_eq!(evaluate(&mut state, "A + A").to_string(), "[[2, 4], [6, 8]]");
+        assert_eq!(evaluate(&mut state, "A - A").to_string(), "[[0, 0], [0, 0]]");
+        assert_eq!(evaluate(&mut state, "2 * A").to_string(), "[[2, 4], [6, 8]]");
+        assert_eq!(evaluate(&mut state, "A / 2").to_string(), "[[1/2, 1], [3/2, 2]]");
+        assert_eq!(evaluate(&mut state, "-A").to_string(), "[[-1, -2], [-3, -4]]");
+        assert_eq!(evaluate(&mut state, "A * [5, 6]").to_string(), "[17, 39]");
+        assert_eq!(evaluate(&mut state, "[5, 6] * A").to_string(), "[23, 34]");
+        assert_eq!(eval("[[1, 2, 3]] * [[1], [2], [3]]").to_string(), "[[14]]");
+
+        assert_eq!(evaluate(&mut state, "A^0").to_string(), "[[1, 0], [0, 1]]");
+        assert_eq!(evaluate(&mut state, "A^3").to_string(), "[[37, 54], [81, 118]]");
+        assert_eq!(evaluate(&mut state, "A^-1 * A").to_string(), "[[1, 0], [0, 1]]");
+        assert_eq!(eval("([[1, 1], [1, 0]]^50)[0][1]"), Object::Integer(12586269025));
+
+        assert_eq!(evaluate(&mut state, "A[1]").to_string(), "[3, 4]");
+        assert_eq!(evaluate(&mut state, "A[1][0]"), Object::Integer(3));
+        assert_eq!(evaluate(&mut state, "len(A)"), Object::Integer(2));
+
+        assert_eq!(
+            eval("transpose([[1, 2, 3], [4, 5, 6]])").to_string(),
+            "[[1, 4], [2, 5], [3, 6]]"
+        );
+        assert_eq!(evaluate(&mut state, "det(A)"), Object::Integer(-2));
+        assert_eq!(eval("det([[2, 0, 1], [1, 3, 2], [1, 1, 2]])"), Object::Integer(6));
+        assert_eq!(eval("det([[0, 1], [1, 0]])"), Object::Integer(-1));
+        assert_eq!(eval("det([[1, 2], [2, 4]])"), Object::Integer(0));
+        assert_eq!(eval("det([[1.5, 2], [3, 4]])"), Object::Integer(0));
+        assert_eq!(evaluate(&mut state, "inverse(A)").to_string(), "[[-2, 1], [3/2, -1/2]]");
+        assert_eq!(eval("rank([[1, 2], [2, 4]])"), Object::Integer(1));
+        assert_eq!(eval("rank([[1, 2, 3], [4, 5, 6], [7, 8, 9.0]])"), Object::Integer(2));
+        assert_eq!(eval("rank([[0, 0], [0, 0]])"), Object::Integer(0));
+        assert_eq!(eval("linsolve([[2, 1], [1, 3]], [3, 5])").to_string(), "[4/5, 7/5]");
+        match eval("linsolve([[0.5, 1], [1, 3]], [2, 5])") {
+            Object::List(ref solution) => {
+                assert!((solution[0].as_float().unwrap() - 2.0).abs() < 1e-12);
+                assert!((solution[1].as_float().unwrap() - 1.0).abs() < 1e-12);
+            }
+            other => panic!("expected a list, got {:?}", other),
+        }
+
+        assert_eq!(
+            message(eval("[[1, 2, 3]] * [[1, 2]]")),
+            "can't multiply a 1 by 3 matrix by a 1 by 2 matrix"
+        );
+        assert_eq!(underline("[[1, 2, 3]] * [[1, 2]]"), "            ^");
+        assert_eq!(
+            message(eval("[[1, 2]] + [[1], [2]]")),
+            "can't add a 1 by 2 matrix and a 2 by 1 matrix"
+        );
+        assert_eq!(message(eval("[[1, 2]] + 1")), "can only add a matrix and another matrix");
+        assert_eq!(
+            message(eval("[[1, 2]] * [1, 2, 3]")),
+            "can't multiply a 1 by 2 matrix by a list of 3"
+        );
+        assert_eq!(message(eval("[[1, 2]] * 1 km")), "matrices can't have units");
+        assert_eq!(
+            message(eval("1 / [[1, 2]]")),
+            "can't divide by a matrix, but you can multiply by its inverse"
+        );
+        assert_eq!(message(eval("[[1, 2]]^2")), "only square matrices can be raised to a power");
+        assert_eq!(
+            message(eval("[[1, 2], [3, 4]]^0.5")),
+            "matrices can only be raised to integer powers"
+        );
+        assert_eq!(
+            message(eval("[[2]]^100000000")),
+            "that number is too large to work out exactly"
+        );
+        assert_eq!(message(eval("det([[1, 2]])")), "only square matrices can have a determinant");
+        assert_eq!(message(eval("det([1, 2])")), "det can only be applied to matrices");
+        assert_eq!(
+            message(eval("inverse([[1, 2], [2, 4]])")),
+            "that matrix is singular, so it has no inverse"
+        );
+        assert_eq!(
+            message(eval("[[1, 2], [2, 4]]^-1")),
+            "that matrix is singular, so it has no inverse"
+        );
+        assert_eq!(
+            message(eval("linsolve([[1, 2], [2, 4]], [1, 2])")),
+            "that matrix is singular, so there isn't exactly one solution"
+        );
+        assert_eq!(
+            message(eval("linsolve([[1, 2], [3, 4]], [1])")),
+            "a 2 by 2 matrix has to be solved against a list of 2 numbers"
+        );
+        assert_eq!(message(eval("[[1, 2]][1]")), "index 1 is out of range for a matrix with 1 row");
+    }
+
+    #[test]
+    fn derivatives() {
+        let derivative = |input| eval(input).to_string();
+        assert_eq!(derivative("diff(x^2, x)"), "2 * x");
+        assert_eq!(derivative("diff(3x^2 + 2x + 1, x)"), "6 * x + 2");
+        assert_eq!(derivative("diff(x^3 / (1 + x), x)"), "(3 * x^2 * (1 + x) - x^3) / (1 + x)^2");
+        assert_eq!(derivative("diff(1/x, x)"), "-1 / x^2");
+        assert_eq!(derivative("diff(x^-2, x)"), "-2 * x^-3");
+        assert_eq!(derivative("diff(a * x^2 + b * x, x)"), "2 * a * x + b");
+        assert_eq!(derivative("diff(x^n, x)"), "n * x^(n - 1)");
+        assert_eq!(derivative("diff(2^x, x)"), "2^x * ln(2)");
+        assert_eq!(derivative("diff(e^(2 * x), x)"), "2 * e^(2 * x)");
+        assert_eq!(derivative("diff(x^x, x)"), "x^x * (ln(x) + 1)");
+        assert_eq!(derivative("diff(sin(x^2), x)"), "2 * cos(x^2) * x");
+        assert_eq!(derivative("diff(-cos(x), x)"), "sin(x)");
+        assert_eq!(derivative("diff(ln(x), x)"), "1 / x");
+        assert_eq!(derivative("diff(log(x, 2), x)"), "1 / (x * ln(2))");
+        assert_eq!(derivative("diff(sqrt(x), x)"), "1 / (2 * sqrt(x))");
+        assert_eq!(derivative("diff(acos(x), x)"), "-1 / sqrt(1 - x^2)");
+        assert_eq!(derivative("diff(exp(-x^2 / 2), x)"), "-(exp(-x^2 / 2) * x)");
+        // Arithmetic between numbers is worked out before differentiating.
+        assert_eq!(derivative("diff(x^(2/3), x)"), "2/3 * x^(-1/3)");
+        assert_eq!(derivative("diff(x^2^3, x)"), "8 * x^7");
+        assert_eq!(derivative("diff(2^(1 + 1) * x^(3 - 1), x)"), "8 * x");
+        assert_eq!(eval("diff(x / (2/3), x)"), ratio(3, 2));
+        assert_eq!(derivative("diff(diff(x^3, x), x)"), "6 * x");
+        assert_eq!(eval("diff(5, x)"), Object::Integer(0));
+        assert_eq!(eval("diff(3x, x)"), Object::Integer(3));
+        assert_eq!(eval("diff(floor(y), x)"), Object::Integer(0));
+
+        // Derivatives are evaluated once their variable has a value.
+        let mut state = State::new();
+        evaluate(&mut state, "d = diff(x^3 - 2x, x)");
+        assert_eq!(evaluate(&mut state, "d").to_string(), "3 * x^2 - 2");
+        evaluate(&mut state, "slope(x) = d");
+        assert_eq!(evaluate(&mut state, "slope(2)"), Object::Integer(10));
+        assert_eq!(evaluate(&mut state, "diff(d, x)").to_string(), "6 * x");
+        evaluate(&mut state, "x = 3");
+        assert_eq!(evaluate(&mut state, "d"), Object::Integer(25));
+        assert_eq!(evaluate(&mut state, "diff(x^2, x)"), Object::Integer(6));
+        assert_eq!(evaluate(&mut state, "d + 1"), Object::Integer(26));
+        // Numbers keep their type, even if they wouldn't when shown and read
+        // back in.
+        evaluate(&mut state, "big = diff(1.5e20 * x^2, x)");
+        assert_eq!(evaluate(&mut state, "big"), Object::Float(9e20));
+        evaluate(&mut state, "tiny = diff(1.5e-20 * x^2, x)");
+        assert_eq!(evaluate(&mut state, "tiny"), Object::Float(9e-20));
+
+        // Functions are expanded into their bodies.
+        evaluate(&mut state, "f(t) = t^3 - 2t");
+        assert_eq!(evaluate(&mut state, "diff(f(u), u)").to_string(), "3 * u^2 - 2");
+        assert_eq!(evaluate(&mut state, "diff(f(2 * u), u)").to_string(), "6 * (2 * u)^2 - 4");
+        evaluate(&mut state, "loop(t) = loop(t) + 1");
+        assert_eq!(message(evaluate(&mut state, "diff(loop(u), u)")), "too much recursion in loop");
+        evaluate(&mut state, "y = diff(y^2, y)");
+        assert_eq!(message(evaluate(&mut state, "y")), "y depends on itself");
+
+        assert_eq!(message(eval("diff(floor(x), x)")), "diff doesn't know the derivative of floor");
+        assert_eq!(underline("diff(floor(x), x)"), "^^^^");
+        assert_eq!(
+            message(eval("diff(x > 1, x)")),
+            "diff only works with numbers, variables, arithmetic and functions"
+        );
+        assert_eq!(underline("diff(x > 1, x)"), "       ^");
+        assert_eq!(
+            message(eval("diff(x^2, 2)")),
+            "diff has to be given the variable to differentiate by, like `diff(x^2, x)`"
+        );
+        assert_eq!(message(eval("diff(x^2)")), "diff takes 2 arguments but 1 was given");
+        assert_eq!(message(eval("diff(g(x), x)")), "no function named g");
+        assert_eq!(message(evaluate(&mut state, "diff(x) = x")), "diff is a built in function");
+        assert_eq!(message(eval("diff(x^2, x) * 2")), "that operation isn't supported");
+    }
+
+    #[test]
+    fn solving() {
+        assert_eq!(eval("solve(x^2 = 2, x)"), Object::Float(2f64.sqrt()));
+        assert_eq!(eval("solve(x^2 = 4, x, -5)"), Object::Float(-2.0));
+        assert_eq!(eval("solve(2x + 1 = 7, x)"), Object::Float(3.0));
+        assert_eq!(eval("solve(ln(x) = 2, x)"), Object::Float(7.3890560989306495));
+        assert_eq!(eval("solve((x - 3)^2 = 0, x)"), Object::Float(3.0));
+        assert_eq!(eval("solve(cos(x) = x, x, 0, 1)"), Object::Float(0.7390851332151607));
+
+        // The monthly rate that pays off 1000 in a year at 88.85 a month.
+        let mut state = State::new();
+        evaluate(&mut state, "payment(r) = 1000 * r / (1 - (1 + r)^-12)");
+        match evaluate(&mut state, "r = 0.5; solve(payment(r) = 88.85, r)") {
+            Object::Float(rate) => assert!((rate - 0.01).abs() < 1e-5),
+            other => panic!("expected a rate, got {}", other),
+        }
+        // The unknown is only bound while solving.
+        assert_eq!(evaluate(&mut state, "r"), Object::Float(0.5));
+
+        assert_eq!(eval("root(sin, 3, 4)"), Object::Float(::std::f64::consts::PI));
+        assert_eq!(eval("f(x) = x^3 - 8; root(f, 0, 5)"), Object::Float(2.0));
+
+        assert_eq!(
+            message(eval("solve(x^2 = -1, x)")),
+            "solve couldn't find a value of x that solves the equation"
+        );
+        assert_eq!(
+            message(eval("solve(1/x = 0, x)")),
+            "solve couldn't find a value of x that solves the equation"
+        );
+        // Both sides only look equal once they're too large or too small to
+        // tell apart.
+        assert_eq!(
+            message(eval("solve(x = x + 1, x)")),
+            "solve couldn't find a value of x that solves the equation"
+        );
+        assert_eq!(
+            message(eval("solve(exp(x) = 0, x)")),
+            "solve couldn't find a value of x that solves the equation"
+        );
+        // Some values on the way are too large, but there's a root before them.
+        assert_eq!(eval("solve(exp(x) = 1e300, x)"), Object::Float(690.7755278982137));
+        assert_eq!(eval("solve(exp(x) = 1e-300, x)"), Object::Float(-690.7755278982137));
+        assert_eq!(
+            message(eval("solve(x^2 = 2, x, 3, 4)")),
+            "the values at 3 and 4 have the same sign, so there's no root between them to find"
+        );
+        assert_eq!(message(eval("solve(x = y, x)")), "no variable named y");
+        assert_eq!(underline("solve(x = y, x)"), "          ^");
+        assert_eq!(
+            message(eval("solve(x, x)")),
+            "solve has to be given an equation, like `solve(x^2 = 2, x)`"
+        );
+        assert_eq!(
+            message(eval("solve(x = 1, 2)")),
+            "solve has to be given the variable to solve for, like `solve(x^2 = 2, x)`"
+        );
+        assert_eq!(message(eval("solve(x = 1)")), "solve takes 2 to 4 arguments but 1 was given");
+        assert_eq!(message(eval("sqrt(x = 2)")), "only solve can be given an equation");
+        assert_eq!(underline("sqrt(x = 2)"), "       ^");
+        assert_eq!(message(eval("solve(x = [1], x)")), "solve only works with numbers");
+        assert_eq!(
+            message(eval("root(sin, 1, 2)")),
+            "the values at 1 and 2 have the same sign, so there's no root between them to find"
+        );
+        assert_eq!(message(eval("root(ln, -1, 2)")), "there's no real value at -1");
+        assert_eq!(message(eval("root(g, 0, 1)")), "no function named g");
+        assert_eq!(
+            message(eval("root(2, 0, 1)")),
+            "root has to be given the name of a function, like `root(f, 0, 1)`"
+        );
+        assert_eq!(message(eval("root(x) = x")), "root is a built in function");
+    }
+
+    #[test]
+    fn integrals() {
+        assert_eq!(eval("integrate(x^2, x, 0, 3)"), Object::Float(9.0));
+        assert_eq!(eval("integrate(sin(x), x, 0, pi)"), Object::Float(2.0));
+        assert_eq!(eval("integrate(x, x, 3, 1)"), Object::Float(-4.0));
+        assert_eq!(eval("integrate(sin(x), x, -1, 1)"), Object::Float(0.0));
+        assert_eq!(
+            eval("integrate(4 / (1 + x^2), x, 0, 1)"),
+            Object::Float(::std::f64::consts::PI)
+        );
+        assert_eq!(eval("integrate(ln(x), x, 0, 1)"), Object::Float(-1.0));
+        match eval("integrate(1/sqrt(x), x, 0, 1)") {
+            Object::Float(area) => assert!((area - 2.0).abs() < 1e-12),
+            other => panic!("expected an area, got {}", other),
+        }
+
+        // The variable is only bound while integrating.
+        let mut state = State::new();
+        evaluate(&mut state, "x = 10; f(t) = t * x");
+        assert_eq!(evaluate(&mut state, "integrate(f(2), x, 0, 1)"), Object::Float(1.0));
+        assert_eq!(evaluate(&mut state, "x"), Object::Integer(10));
+
+        assert_eq!(message(eval("integrate(y, x, 0, 1)")), "no variable named y");
+        assert_eq!(
+            message(eval("integrate(sqrt(x), x, -1, 1)")),
+            "that isn't a finite real number at x = -0.9914553711208126"
+        );
+        assert_eq!(underline("integrate(sqrt(x), x, -1, 1)"), "          ^^^^^^^");
+        assert_eq!(message(eval("integrate(x > 1, x, 0, 1)")), "integrate only works with numbers");
+        assert_eq!(
+            message(eval("integrate(x, 2, 0, 1)")),
+            "integrate has to be given the variable to integrate over, \
+             like `integrate(x^2, x, 0, 1)`"
+        );
+        assert_eq!(
+            message(eval("integrate(x, x, 0)")),
+            "integrate takes 4 arguments but 3 were given"
+        );
+        assert_eq!(
+            message(eval("integrate(x, x, 0, 1e400)")),
+            "integrate can only go between finite bounds"
+        );
+        // The bounds are finite, but the area isn't.
+        assert_eq!(message(eval("integrate(1e308, x, 0, 1)")), "that number is too large");
+        assert_eq!(message(eval("integrate(x, x, 0, 1e300)")), "that number is too large");
+        assert_eq!(message(eval("integrate(x^2, x, 0, 1e110)")), "that number is too large");
+        assert_eq!(underline("integrate(x^2, x, 0, 1e110)"), "^^^^^^^^^");
+    }
+
+    #[test]
+    fn series() {
+        assert_eq!(eval("sum(k^2, k, 1, 10)"), Object::Integer(385));
+        assert_eq!(eval("sum(1/k^2, k, 1, 3)"), ratio(49, 36));
+        assert_eq!(eval("prod(k, k, 1, 30)"), big("265252859812191058636308480000000"));
+        assert_eq!(eval("sum(k, k, 5, 1)"), Object::Integer(0));
+        assert_eq!(eval("prod(k, k, 5, 1)"), Object::Integer(1));
+        assert_eq!(eval("sum([k, 1], k, 1, 3)").to_string(), "[6, 3]");
+        assert_eq!(eval("sum(k * 1 m, k, 1, 3)").to_string(), "6 m");
+        // Four arguments that don't name a new variable are still just added
+        // up, even if one of them is a variable.
+        assert_eq!(eval("sum(1, 2, 3, 4)"), Object::Integer(10));
+        let mut state = State::new();
+        evaluate(&mut state, "a = 1; b = 2; c = 3; d = 4; x = 5");
+        assert_eq!(evaluate(&mut state, "sum(a, b, c, d)"), Object::Integer(10));
+        assert_eq!(evaluate(&mut state, "sum(1, x, 2, 3)"), Object::Integer(11));
+        assert_eq!(evaluate(&mut state, "sum(a, k, c, d)"), Object::Integer(2));
+        assert_eq!(evaluate(&mut state, "sum(k * x, k, a, d)"), Object::Integer(50));
+
+        // Long series of fractions carry on as floats.
+        match eval("sum(1/k^2, k, 1, 1000)") {
+            Object::Float(total) => assert!((total - 1.6439345666815615).abs() < 1e-12),
+            other => panic!("expected a float, got {}", other),
+        }
+
+        let mut state = State::new();
+        evaluate(&mut state, "k = 7; factorial(n) = prod(k, k, 1, n)");
+        assert_eq!(evaluate(&mut state, "factorial(5)"), Object::Integer(120));
+        assert_eq!(evaluate(&mut state, "k"), Object::Integer(7));
+
+        assert_eq!(
+            message(eval("sum(k, k, 1, 2000000)")),
+            "sum can only go through 1000000 terms at a time"
+        );
+        assert_eq!(message(eval("sum(k, k, 1.5, 3)")), "sum can only count between integers");
+        assert_eq!(underline("sum(k, k, 1.5, 3)"), "          ^^^");
+        assert_eq!(message(eval("sum(1/(k - 2), k, 1, 3)")), "division by zero");
+        assert_eq!(
+            message(eval("prod(k, 2, 1, 3)")),
+            "prod has to be given the variable to count with, like `prod(k^2, k, 1, 10)`"
+        );
+        assert_eq!(message(eval("prod(k, k, 1)")), "prod takes 4 arguments but 3 were given");
+        assert_eq!(message(eval("prod(x) = x")), "prod is a built in function");
+    }
+
+    #[test]
+    fn plots() {
+        let plot = match eval("plot(x^2, x, -1, 1)") {
+            Object::Plot(plot) => plot,
+            other => panic!("expected a plot, got {}", other),
+        };
+        assert_eq!(plot.variable, "x");
+        assert_eq!(plot.series.len(), 1);
+        assert_eq!(plot.series[0].label, "x^2");
+        assert_eq!(plot.series[0].values.len(), plot.points().len());
+        assert_eq!(plot.series[0].values[0], Some(1.0));
+        assert_eq!(plot.series[0].values[100], Some(0.0));
+        let svg = plot.to_string();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>"));
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        assert!(svg.contains(">-0.5</text>"));
+        assert!(svg.contains(">0.8</text>"));
+
+        // Tick labels that would be too long for the margin use exponents.
+        let labels = |input| {
+            let svg = eval(input).to_string();
+            let texts = svg.split("</text>").filter(|text| text.contains("<text"));
+            texts.map(|text| text.rsplit('>').next().unwrap().to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            labels("plot(x, x, 0, 1e-9)")[..6],
+            ["0", "2e-10", "4e-10", "6e-10", "8e-10", "1e-9"]
+        );
+        assert_eq!(labels("plot(x, x, 0, 3e9)")[..4], ["0", "5e8", "1e9", "1.5e9"]);
+        assert_eq!(labels("plot(x, x, 0, 3e-4)")[..4], ["0", "5e-5", "1e-4", "1.5e-4"]);
+        // Unless they are just as long that way.
+        assert_eq!(labels("plot(x, x, 1e6, 1e6 + 1)")[..2], ["1000000.0", "1000000.2"]);
+        // A range too narrow to tell its ends apart has no ticks along it.
+        assert_eq!(
+            labels("plot(x, x, 0, 5e-324)")[..],
+            ["-1.0", "-0.5", "0.0", "0.5", "1.0", "x"]
+        );
+
+        // Each expression in a list is its own line, and gaps where there is
+        // no real value break the line up.
+        let plot = match eval("plot([1/x, sqrt(x)], x, -1, 1)") {
+            Object::Plot(plot) => plot,
+            other => panic!("expected a plot, got {}", other),
+        };
+        let labels: Vec<&str> = plot.series.iter().map(|series| series.label.as_str()).collect();
+        assert_eq!(labels, ["1/x", "sqrt(x)"]);
+        assert_eq!(plot.series[0].values[100], None);
+        assert_eq!(plot.series[1].values[0], None);
+        let svg = plot.to_string();
+        assert_eq!(svg.matches("<polyline").count(), 4);
+        assert!(svg.contains(">sqrt(x)</text>"));
+
+        let mut state = State::new();
+        evaluate(&mut state, "x = 5");
+        assert!(matches!(evaluate(&mut state, "plot(sin(x), x, 0, 2pi)"), Object::Plot(_)));
+        assert_eq!(evaluate(&mut state, "x"), Object::Integer(5));
+
+        assert_eq!(message(eval("plot(y, x, 0, 1)")), "no variable named y");
+        assert_eq!(message(eval("plot(x > 1, x, 0, 1)")), "plot only works with numbers");
+        assert_eq!(underline("plot(x > 1, x, 0, 1)"), "     ^^^^^");
+        assert_eq!(
+            message(eval("plot(sqrt(x), x, -2, -1)")),
+            "there's nothing to plot, since that isn't a real number anywhere from -2 to -1"
+        );
+        assert_eq!(
+            message(eval("plot(x, x, 1, 1)")),
+            "plot needs two different, finite ends to plot between"
+        );
+        assert_eq!(
+            message(eval("plot(x, 2, 0, 1)")),
+            "plot has to be given the variable to plot against, like `plot(x^2, x, 0, 1)`"
+        );
+        assert_eq!(message(eval("plot(x)")), "plot takes 4 arguments but 1 was given");
+        assert_eq!(message(eval("plot(x) = x")), "plot is a built in function");
+    }
+
+    #[test]
+    fn info() {
+        assert_eq!(eval("help()"), Object::Info(InfoType::Help));
+        assert_eq!(eval("about()"), Object::Info(InfoType::About));
+    }
+}
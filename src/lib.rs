@@ -0,0 +1,30 @@
+//! The calculator engine behind `calc_rs`.
+//!
+//! Nothing in this library touches the DOM, so it can be embedded in native
+//! tools and tested with a plain `cargo test`. The web front end lives in
+//! `main.rs` and is only built with the `web` feature.
+
+#[macro_use]
+extern crate serde_derive;
+extern crate serde;
+extern crate serde_json;
+#[macro_use]
+extern crate pest_derive;
+#[allow(unused_imports)]
+#[macro_use]
+extern crate pest;
+#[macro_use]
+extern crate lazy_static;
+extern crate num_bigint;
+extern crate num_complex;
+extern crate num_integer;
+extern crate num_rational;
+extern crate num_traits;
+
+mod builtins;
+pub mod engine;
+mod model;
+mod numeric;
+mod parse;
+pub mod storage;
+mod units;
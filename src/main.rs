@@ -1,43 +1,24 @@
+extern crate calc_rs;
 #[macro_use]
 extern crate stdweb;
-#[macro_use]
-extern crate serde_derive;
-extern crate serde;
-extern crate serde_json;
-#[macro_use]
-extern crate pest_derive;
-#[macro_use]
-extern crate pest;
-#[macro_use]
-extern crate lazy_static;
-
-use pest::{iterators::Pair,
-           prec_climber::{Assoc, Operator, PrecClimber},
-           Parser};
-use std::{cell::RefCell,
-          error::Error,
-          ops::{Add, Div, Mul, Rem, Sub},
-          rc::Rc};
+
+use calc_rs::engine::{evaluate, Object, State};
+use calc_rs::storage;
+use std::{cell::RefCell, rc::Rc};
 use stdweb::{traits::*,
              unstable::TryInto,
              web::{document,
+                   Date,
                    event::{InputEvent, KeyPressEvent},
                    window,
                    HtmlElement}};
 
-mod model;
-use model::{InfoType, Object, State, StateRef};
-mod parse;
-use parse::{CalcParser, Rule};
-
-lazy_static! {
-    static ref PREC_CLIMBER: PrecClimber<Rule> = PrecClimber::new(vec![
-        Operator::new(Rule::sub, Assoc::Left) | Operator::new(Rule::add, Assoc::Left),
-        Operator::new(Rule::mul, Assoc::Left) | Operator::new(Rule::div, Assoc::Left),
-        Operator::new(Rule::pow, Assoc::Right),
-        Operator::new(Rule::rem, Assoc::Left),
-    ]);
-}
+mod display;
+use display::display;
+
+type StateRef = Rc<RefCell<State>>;
+
+const PROMPT: &str = "calc_rs > ";
 
 // Shamelessly stolen from stdweb's TodoMVC example.
 macro_rules! enclose {
@@ -66,8 +47,8 @@ fn main() {
 
     first_prompt.set_text_content("about()");
 
-    let result = eval(&state, "about()");
-    show(&state, result);
+    let result = evaluate(&mut state.borrow_mut(), "about()");
+    show(&state, "about()", result);
     new_prompt(&state);
 }
 
@@ -80,9 +61,9 @@ fn add_input_events(state: &StateRef, element: &HtmlElement) {
 
             if !entry.chars().all(char::is_whitespace) {
                 state.borrow_mut().add_entry(&entry);
-                let result = eval(&state, &entry);
+                let result = evaluate(&mut state.borrow_mut(), &entry);
                 state.borrow_mut().set_ans(&result);
-                show(&state, result);
+                show(&state, &entry, result);
                 new_prompt(&state);
                 set_storage(&state);
             } else {
@@ -108,7 +89,10 @@ fn add_input_events(state: &StateRef, element: &HtmlElement) {
     element.add_event_listener(enclose!( (element, state) move |_event: InputEvent| {
         let incomplete: String = element.inner_text();
             if !incomplete.chars().all(char::is_whitespace) {
-                let result = eval(&state, &incomplete);
+                // Preview on a copy, so that typing doesn't assign variables
+                // or change `ans` before the line is entered.
+                let mut preview = state.borrow().clone();
+                let result = evaluate(&mut preview, &incomplete);
                 match result {
                     Object::Error(_text) => element.class_list().add("error").unwrap(),
                     _ => element.class_list().remove("error").unwrap()
@@ -117,71 +101,10 @@ fn add_input_events(state: &StateRef, element: &HtmlElement) {
     }));
 }
 
-fn eval(state: &StateRef, input: &str) -> Object {
-    // follows P E (M | D) (A | S)
-
-    let pairs = match CalcParser::parse(Rule::statement, input) {
-        Ok(pairs) => pairs,
-        Err(_error) => return Object::Error("parsing error".to_string()),
-    };
-
-    // TODO: implement rational and power of ten exponents, maybe even bignums,
-    // fix negative numbers, testing, order of ops working, responsive design,
-    // make help messages work better, comment code, add readme, separate parse
-    // into a library, add desktop gui, etc.
-    fn consume(state: &StateRef, pair: Pair<Rule>) -> Object {
-        match pair.as_rule() {
-            Rule::assn => {
-                // In an assignment, there must be exactly 2 pairs: `a = b`,
-                // where a is a symbol and b is some kind of expression.
-                let mut inner = pair.into_inner();
-                let left = inner.next(); // symbol
-                let right = consume(state, inner.next().unwrap()); // expr
-
-                state // Insert the assignment
-                    .borrow_mut()
-                    .assignments
-                    .insert(left.unwrap().as_str().to_string(), right);
-                Object::Nil // and return nil to the machine.
-            }
-            Rule::expr => {
-                let primary = |pair| consume(state, pair);
-
-                let infix = |lhs: Object, op: Pair<Rule>, rhs: Object| match op.as_rule() {
-                    Rule::pow => lhs.pow(rhs),
-                    Rule::add => lhs.add(rhs),
-                    Rule::sub => lhs.sub(rhs),
-                    Rule::mul => lhs.mul(rhs),
-                    Rule::div => lhs.div(rhs),
-                    Rule::rem => lhs.rem(rhs),
-                    _ => unreachable!(),
-                };
-
-                PREC_CLIMBER.climb(pair.into_inner(), primary, infix)
-            }
-            Rule::symbol => match state.borrow().assignments.get(pair.as_str()) {
-                Some(obj) => obj.clone(),
-                None => Object::Error(format!("no variable named {}", pair.as_str())),
-            },
-            Rule::int => pair.as_str().parse::<i64>().unwrap().into(),
-            Rule::float => pair.as_str().parse::<f64>().unwrap().into(),
-            Rule::rational => unimplemented!(),
-            Rule::help => Object::Info(InfoType::Help),
-            Rule::about => Object::Info(InfoType::About),
-            _ => unreachable!(),
-        }
-    }
-
-    match pairs.clone().next() {
-        Some(pair) => consume(state, pair),
-        None => Object::Error("".to_string()),
-    }
-}
-
-fn show(_state: &StateRef, output: Object) {
+fn show(_state: &StateRef, input: &str, output: Object) {
     // Ask the output to construct a DOM to display itself, and then see if it
     // gives one.
-    match output.display() {
+    match display(output, input) {
         Some(html) => {
             // If it does, find the console then add the DOM.
             let console: HtmlElement = document()
@@ -246,7 +169,7 @@ fn new_prompt(state: &StateRef) {
         .try_into()
         .unwrap();
     new_prompt.class_list().add("prompt").unwrap();
-    new_prompt.append_child(&document().create_text_node("calc_rs > "));
+    new_prompt.append_child(&document().create_text_node(PROMPT));
 
     // Construct the contenteditable p element that works as an input.
     let new_input: HtmlElement = document().create_element("p").unwrap().try_into().unwrap();
@@ -271,13 +194,25 @@ fn new_prompt(state: &StateRef) {
 fn set_storage(state: &StateRef) {
     let storage = window().local_storage();
     let state = state.clone();
-    let string = serde_json::to_string(&*state.borrow()).unwrap();
+    let string = storage::save(&state.borrow());
     storage.insert("state", &string).unwrap();
 }
 
 fn get_storage() -> Option<State> {
     let storage = window().local_storage();
-    storage
-        .get("state")
-        .map(|string| serde_json::from_str(&string).unwrap_or(State::new()))
+    let loaded = storage::load(&storage.get("state")?);
+
+    for problem in &loaded.problems {
+        console!(error, format!("calc_rs: {}", problem));
+    }
+    // Keep the old state under its own key, since it is about to be
+    // overwritten with only the parts that could be read.
+    if let Some(backup) = loaded.backup {
+        let key = format!("state.backup-{}", Date::now() as u64);
+        if storage.insert(&key, &backup).is_ok() {
+            console!(error, format!("calc_rs: the old state was kept in localStorage as {}", key));
+        }
+    }
+
+    Some(loaded.state)
 }
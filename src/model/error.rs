@@ -0,0 +1,150 @@
+use std::fmt;
+
+/// A range of bytes in the input that an error is about.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+/// Everything that can go wrong while evaluating a line.
+///
+/// Errors that come out of arithmetic on `Object`s don't know where in the
+/// input they happened, so their span starts out as `None` and the evaluator
+/// fills it in with the operator or call that caused them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CalcError {
+    Parse { message: String, span: Option<Span> },
+    UnknownVariable { name: String, span: Option<Span> },
+    UnknownFunction { name: String, span: Option<Span> },
+    UnknownUnit { name: String, span: Option<Span> },
+    TypeMismatch { message: String, span: Option<Span> },
+    DivisionByZero { span: Option<Span> },
+    Overflow { message: String, span: Option<Span> },
+    Domain { message: String, span: Option<Span> },
+    Definition { message: String, span: Option<Span> },
+    Units { message: String, span: Option<Span> },
+}
+
+impl CalcError {
+    pub fn type_mismatch<S: Into<String>>(message: S) -> Self {
+        CalcError::TypeMismatch {
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    pub fn overflow<S: Into<String>>(message: S) -> Self {
+        CalcError::Overflow {
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    pub fn domain<S: Into<String>>(message: S) -> Self {
+        CalcError::Domain {
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    pub fn units<S: Into<String>>(message: S) -> Self {
+        CalcError::Units {
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    /// The error for applying an operator to things it doesn't work on.
+    pub fn unsupported() -> Self {
+        CalcError::type_mismatch("that operation isn't supported")
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            CalcError::Parse { span, .. }
+            | CalcError::UnknownVariable { span, .. }
+            | CalcError::UnknownFunction { span, .. }
+            | CalcError::UnknownUnit { span, .. }
+            | CalcError::TypeMismatch { span, .. }
+            | CalcError::DivisionByZero { span }
+            | CalcError::Overflow { span, .. }
+            | CalcError::Domain { span, .. }
+            | CalcError::Definition { span, .. }
+            | CalcError::Units { span, .. } => span,
+        }
+    }
+
+    fn span_mut(&mut self) -> &mut Option<Span> {
+        match *self {
+            CalcError::Parse { ref mut span, .. }
+            | CalcError::UnknownVariable { ref mut span, .. }
+            | CalcError::UnknownFunction { ref mut span, .. }
+            | CalcError::UnknownUnit { ref mut span, .. }
+            | CalcError::TypeMismatch { ref mut span, .. }
+            | CalcError::DivisionByZero { ref mut span }
+            | CalcError::Overflow { ref mut span, .. }
+            | CalcError::Domain { ref mut span, .. }
+            | CalcError::Definition { ref mut span, .. }
+            | CalcError::Units { ref mut span, .. } => span,
+        }
+    }
+
+    /// Points the error at `span`, replacing wherever it pointed before.
+    pub fn at(mut self, span: Span) -> Self {
+        *self.span_mut() = Some(span);
+        self
+    }
+
+    /// Points the error at `span` if it doesn't point anywhere yet.
+    pub fn or_at(self, span: Span) -> Self {
+        match self.span() {
+            Some(_) => self,
+            None => self.at(span),
+        }
+    }
+
+    /// A line of carets under the part of `input` the error is about, meant
+    /// to be shown directly below it. Returns `None` if the error has no
+    /// span.
+    pub fn underline(&self, input: &str) -> Option<String> {
+        let span = self.span()?;
+        let starts = input.char_indices().map(|(index, _)| index);
+
+        let padding = starts.clone().filter(|&index| index < span.start).count();
+        let width = starts
+            .filter(|&index| index >= span.start && index < span.end)
+            .count()
+            .max(1);
+        Some(format!("{}{}", " ".repeat(padding), "^".repeat(width)))
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CalcError::Parse { ref message, .. } => write!(f, "{}", message),
+            CalcError::UnknownVariable { ref name, .. } => {
+                write!(f, "no variable named {}", name)
+            }
+            CalcError::UnknownFunction { ref name, .. } => {
+                write!(f, "no function named {}", name)
+            }
+            CalcError::UnknownUnit { ref name, .. } => {
+                write!(f, "no unit or variable named {}", name)
+            }
+            CalcError::TypeMismatch { ref message, .. } => write!(f, "{}", message),
+            CalcError::DivisionByZero { .. } => write!(f, "division by zero"),
+            CalcError::Overflow { ref message, .. } => write!(f, "{}", message),
+            CalcError::Domain { ref message, .. } => write!(f, "{}", message),
+            CalcError::Definition { ref message, .. } => write!(f, "{}", message),
+            CalcError::Units { ref message, .. } => write!(f, "{}", message),
+        }
+    }
+}
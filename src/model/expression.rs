@@ -0,0 +1,470 @@
+use std::{cmp::Ordering,
+          collections::HashMap,
+          fmt,
+          ops::{Add, Div, Mul, Sub}};
+
+use super::{CalcError, Object};
+
+/// An arithmetic expression kept as a tree instead of being evaluated, so
+/// that it can be worked with symbolically, like by `diff`.
+///
+/// Shown as text, an expression is valid input again, so that it can be
+/// copied from the output.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Expression {
+    Number(Object),
+    Symbol(String),
+    Neg(Box<Expression>),
+    Add(Box<Expression>, Box<Expression>),
+    Sub(Box<Expression>, Box<Expression>),
+    Mul(Box<Expression>, Box<Expression>),
+    Div(Box<Expression>, Box<Expression>),
+    Pow(Box<Expression>, Box<Expression>),
+    /// A call to a built in function. Calls to functions the user defined
+    /// are replaced by their bodies when the tree is built.
+    Call(String, Vec<Expression>),
+}
+
+/// An expression that is waiting for `variable` to have a value, like the
+/// result of `diff(x^2, x)`. It is evaluated as soon as the variable is
+/// assigned or bound to an argument.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Symbolic {
+    pub expression: Expression,
+    pub variable: String,
+}
+
+// How tightly each kind of expression binds, to know where parentheses are
+// needed when it is shown.
+const SUM: u8 = 1;
+const PRODUCT: u8 = 2;
+const NEGATION: u8 = 3;
+const ATOM: u8 = 4;
+
+impl Expression {
+    fn number(value: i64) -> Expression {
+        Expression::Number(Object::Integer(value))
+    }
+
+    fn call(name: &str, arg: Expression) -> Expression {
+        Expression::Call(name.to_string(), vec![arg])
+    }
+
+    /// Whether the expression is the number `value`.
+    fn is(&self, value: i64) -> bool {
+        match *self {
+            Expression::Number(ref number) => {
+                number.compare(&Object::Integer(value)) == Some(Ordering::Equal)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether `variable` appears anywhere in the expression.
+    pub fn depends_on(&self, variable: &str) -> bool {
+        match *self {
+            Expression::Number(_) => false,
+            Expression::Symbol(ref name) => name == variable,
+            Expression::Neg(ref inner) => inner.depends_on(variable),
+            Expression::Add(ref lhs, ref rhs)
+            | Expression::Sub(ref lhs, ref rhs)
+            | Expression::Mul(ref lhs, ref rhs)
+            | Expression::Div(ref lhs, ref rhs)
+            | Expression::Pow(ref lhs, ref rhs) => {
+                lhs.depends_on(variable) || rhs.depends_on(variable)
+            }
+            Expression::Call(_, ref args) => args.iter().any(|arg| arg.depends_on(variable)),
+        }
+    }
+
+    /// Replaces each symbol named in `bindings` with its expression, which
+    /// is how calls to functions the user defined are expanded. Numbers that
+    /// end up next to each other are worked out.
+    pub fn substitute(self, bindings: &HashMap<String, Expression>) -> Expression {
+        let substitute = |inner: Box<Expression>| inner.substitute(bindings);
+        match self {
+            Expression::Symbol(name) => match bindings.get(&name) {
+                Some(expression) => expression.clone(),
+                None => Expression::Symbol(name),
+            },
+            Expression::Number(_) => self,
+            Expression::Neg(inner) => Expression::negation(substitute(inner)),
+            Expression::Add(lhs, rhs) => Expression::sum(substitute(lhs), substitute(rhs)),
+            Expression::Sub(lhs, rhs) => Expression::difference(substitute(lhs), substitute(rhs)),
+            Expression::Mul(lhs, rhs) => Expression::product(substitute(lhs), substitute(rhs)),
+            Expression::Div(lhs, rhs) => Expression::quotient(substitute(lhs), substitute(rhs)),
+            Expression::Pow(lhs, rhs) => Expression::power(substitute(lhs), substitute(rhs)),
+            Expression::Call(name, args) => Expression::Call(
+                name,
+                args.into_iter().map(|arg| arg.substitute(bindings)).collect(),
+            ),
+        }
+    }
+
+    /// The derivative with respect to `variable`, simplified as it is built.
+    pub fn derivative(&self, variable: &str) -> Result<Expression, CalcError> {
+        if !self.depends_on(variable) {
+            return Ok(Expression::number(0));
+        }
+        Ok(match *self {
+            // Anything without the variable in it has already been handled,
+            // so this is the variable itself.
+            Expression::Number(_) => Expression::number(0),
+            Expression::Symbol(_) => Expression::number(1),
+            Expression::Neg(ref inner) => Expression::negation(inner.derivative(variable)?),
+            Expression::Add(ref lhs, ref rhs) => {
+                Expression::sum(lhs.derivative(variable)?, rhs.derivative(variable)?)
+            }
+            Expression::Sub(ref lhs, ref rhs) => {
+                Expression::difference(lhs.derivative(variable)?, rhs.derivative(variable)?)
+            }
+            // The product rule.
+            Expression::Mul(ref lhs, ref rhs) => Expression::sum(
+                Expression::product(lhs.derivative(variable)?, (**rhs).clone()),
+                Expression::product((**lhs).clone(), rhs.derivative(variable)?),
+            ),
+            Expression::Div(ref lhs, ref rhs) if !rhs.depends_on(variable) => {
+                Expression::quotient(lhs.derivative(variable)?, (**rhs).clone())
+            }
+            // The quotient rule.
+            Expression::Div(ref lhs, ref rhs) => Expression::quotient(
+                Expression::difference(
+                    Expression::product(lhs.derivative(variable)?, (**rhs).clone()),
+                    Expression::product((**lhs).clone(), rhs.derivative(variable)?),
+                ),
+                Expression::power((**rhs).clone(), Expression::number(2)),
+            ),
+            Expression::Pow(ref base, ref exponent) => {
+                power_derivative(base, exponent, variable)?
+            }
+            // The chain rule.
+            Expression::Call(ref name, ref args) => match (name.as_str(), args.as_slice()) {
+                ("log", [arg, base]) => Expression::quotient(
+                    Expression::call("ln", arg.clone()),
+                    Expression::call("ln", base.clone()),
+                ).derivative(variable)?,
+                (name, [arg]) => {
+                    Expression::product(outer_derivative(name, arg)?, arg.derivative(variable)?)
+                }
+                (name, _) => return Err(no_derivative(name)),
+            },
+        })
+    }
+
+    // The constructors below simplify as they go, so that derivatives come
+    // out as something a person would write, like `2 * x` rather than
+    // `2 * x^(2 - 1) * 1`. Arithmetic between numbers is done right away, so
+    // trees are built with them too.
+
+    pub fn negation(inner: Expression) -> Expression {
+        match inner {
+            Expression::Number(number) => match -number.clone() {
+                Object::Error(_) => Expression::Neg(Box::new(Expression::Number(number))),
+                negated => Expression::Number(negated),
+            },
+            Expression::Neg(inner) => *inner,
+            // The sign goes on a leading number, so `-(2 * x)` is `-2 * x`.
+            Expression::Mul(lhs, rhs) => match *lhs {
+                Expression::Number(lhs) => {
+                    Expression::product(Expression::negation(Expression::Number(lhs)), *rhs)
+                }
+                lhs => Expression::Neg(Box::new(Expression::Mul(Box::new(lhs), rhs))),
+            },
+            // And on the top of a fraction, so `-(x^2 / 2)` is `-x^2 / 2`.
+            Expression::Div(lhs, rhs) => match *lhs {
+                Expression::Number(lhs) => {
+                    Expression::quotient(Expression::negation(Expression::Number(lhs)), *rhs)
+                }
+                lhs => Expression::Div(Box::new(Expression::negation(lhs)), rhs),
+            },
+            inner => Expression::Neg(Box::new(inner)),
+        }
+    }
+
+    pub fn sum(lhs: Expression, rhs: Expression) -> Expression {
+        match (lhs, rhs) {
+            (Expression::Number(lhs), Expression::Number(rhs)) => {
+                arithmetic(lhs, rhs, Add::add, Expression::Add)
+            }
+            (ref zero, other) | (other, ref zero) if zero.is(0) => other,
+            (lhs, Expression::Neg(rhs)) => Expression::difference(lhs, *rhs),
+            (Expression::Neg(lhs), rhs) => Expression::difference(rhs, *lhs),
+            (lhs, rhs) => Expression::Add(Box::new(lhs), Box::new(rhs)),
+        }
+    }
+
+    pub fn difference(lhs: Expression, rhs: Expression) -> Expression {
+        match (lhs, rhs) {
+            (Expression::Number(lhs), Expression::Number(rhs)) => {
+                arithmetic(lhs, rhs, Sub::sub, Expression::Sub)
+            }
+            (lhs, ref zero) if zero.is(0) => lhs,
+            (ref zero, rhs) if zero.is(0) => Expression::negation(rhs),
+            (ref lhs, ref rhs) if lhs == rhs => Expression::number(0),
+            (lhs, Expression::Neg(rhs)) => Expression::sum(lhs, *rhs),
+            (lhs, rhs) => Expression::Sub(Box::new(lhs), Box::new(rhs)),
+        }
+    }
+
+    pub fn product(lhs: Expression, rhs: Expression) -> Expression {
+        match (lhs, rhs) {
+            (Expression::Number(lhs), Expression::Number(rhs)) => {
+                arithmetic(lhs, rhs, Mul::mul, Expression::Mul)
+            }
+            (ref zero, _) | (_, ref zero) if zero.is(0) => Expression::number(0),
+            (ref one, other) | (other, ref one) if one.is(1) => other,
+            (ref minus_one, other) | (other, ref minus_one) if minus_one.is(-1) => {
+                Expression::negation(other)
+            }
+            (Expression::Neg(lhs), rhs) => Expression::negation(Expression::product(*lhs, rhs)),
+            (lhs, Expression::Neg(rhs)) => Expression::negation(Expression::product(lhs, *rhs)),
+            // `1/g * a` is `a / g`.
+            (Expression::Div(numer, denom), other) | (other, Expression::Div(numer, denom))
+                if numer.is(1) =>
+            {
+                Expression::quotient(other, *denom)
+            }
+            // Numbers go to the front, where they can be multiplied together.
+            (lhs, Expression::Number(rhs)) => Expression::product(Expression::Number(rhs), lhs),
+            (Expression::Number(lhs), Expression::Mul(inner, rhs)) => match *inner {
+                Expression::Number(inner) => {
+                    let number =
+                        Expression::product(Expression::Number(lhs), Expression::Number(inner));
+                    Expression::product(number, *rhs)
+                }
+                inner => Expression::Mul(
+                    Box::new(Expression::Number(lhs)),
+                    Box::new(Expression::Mul(Box::new(inner), rhs)),
+                ),
+            },
+            (lhs, Expression::Mul(inner, rhs)) => match *inner {
+                Expression::Number(inner) => {
+                    Expression::product(Expression::Number(inner), Expression::product(lhs, *rhs))
+                }
+                inner => Expression::Mul(
+                    Box::new(lhs),
+                    Box::new(Expression::Mul(Box::new(inner), rhs)),
+                ),
+            },
+            (lhs, rhs) => Expression::Mul(Box::new(lhs), Box::new(rhs)),
+        }
+    }
+
+    pub fn quotient(lhs: Expression, rhs: Expression) -> Expression {
+        match (lhs, rhs) {
+            (Expression::Number(lhs), Expression::Number(rhs)) => {
+                arithmetic(lhs, rhs, Div::div, Expression::Div)
+            }
+            (ref zero, _) if zero.is(0) => Expression::number(0),
+            (lhs, ref one) if one.is(1) => lhs,
+            (ref lhs, ref rhs) if lhs == rhs => Expression::number(1),
+            (Expression::Neg(lhs), rhs) => Expression::negation(Expression::quotient(*lhs, rhs)),
+            // `a / b / c` is `a / (b * c)`.
+            (Expression::Div(lhs, inner), rhs) => {
+                Expression::quotient(*lhs, Expression::product(*inner, rhs))
+            }
+            // `6 * x / 2` is `3 * x`.
+            (Expression::Mul(lhs, rest), Expression::Number(rhs)) => match *lhs {
+                Expression::Number(lhs) => Expression::product(
+                    Expression::quotient(Expression::Number(lhs), Expression::Number(rhs)),
+                    *rest,
+                ),
+                lhs => Expression::Div(
+                    Box::new(Expression::Mul(Box::new(lhs), rest)),
+                    Box::new(Expression::Number(rhs)),
+                ),
+            },
+            (lhs, rhs) => Expression::Div(Box::new(lhs), Box::new(rhs)),
+        }
+    }
+
+    pub fn power(base: Expression, exponent: Expression) -> Expression {
+        match (base, exponent) {
+            (_, ref zero) if zero.is(0) => Expression::number(1),
+            (base, ref one) if one.is(1) => base,
+            (Expression::Number(base), Expression::Number(exponent)) => {
+                arithmetic(base, exponent, Object::pow, Expression::Pow)
+            }
+            (base, exponent) => Expression::Pow(Box::new(base), Box::new(exponent)),
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match *self {
+            Expression::Number(ref number) => number_precedence(number),
+            Expression::Symbol(_) | Expression::Call(..) => ATOM,
+            Expression::Neg(_) | Expression::Pow(..) => NEGATION,
+            Expression::Add(..) | Expression::Sub(..) => SUM,
+            Expression::Mul(..) | Expression::Div(..) => PRODUCT,
+        }
+    }
+}
+
+/// The derivative of `base^exponent`, depending on which of them has the
+/// variable in it.
+fn power_derivative(
+    base: &Expression,
+    exponent: &Expression,
+    variable: &str,
+) -> Result<Expression, CalcError> {
+    let power = Expression::Pow(Box::new(base.clone()), Box::new(exponent.clone()));
+    let ln_base = match *base {
+        Expression::Symbol(ref name) if name == "e" => Expression::number(1),
+        _ => Expression::call("ln", base.clone()),
+    };
+
+    Ok(if !exponent.depends_on(variable) {
+        // `d/dx u^n` is `n u^(n - 1) u'`.
+        Expression::product(
+            Expression::product(
+                exponent.clone(),
+                Expression::power(
+                    base.clone(),
+                    Expression::difference(exponent.clone(), Expression::number(1)),
+                ),
+            ),
+            base.derivative(variable)?,
+        )
+    } else if !base.depends_on(variable) {
+        // `d/dx a^v` is `a^v ln(a) v'`.
+        Expression::product(
+            Expression::product(power, ln_base),
+            exponent.derivative(variable)?,
+        )
+    } else {
+        // `d/dx u^v` is `u^v (v' ln(u) + v u' / u)`.
+        Expression::product(
+            power,
+            Expression::sum(
+                Expression::product(exponent.derivative(variable)?, ln_base),
+                Expression::quotient(
+                    Expression::product(exponent.clone(), base.derivative(variable)?),
+                    base.clone(),
+                ),
+            ),
+        )
+    })
+}
+
+/// The derivative of a built in function of one argument, evaluated at
+/// `arg`, before the chain rule multiplies it by the derivative of `arg`.
+fn outer_derivative(name: &str, arg: &Expression) -> Result<Expression, CalcError> {
+    let arg = arg.clone();
+    let one = || Expression::number(1);
+    let squared = |arg| Expression::power(arg, Expression::number(2));
+    Ok(match name {
+        "sin" => Expression::call("cos", arg),
+        "cos" => Expression::negation(Expression::call("sin", arg)),
+        "tan" => Expression::quotient(one(), squared(Expression::call("cos", arg))),
+        "exp" => Expression::call("exp", arg),
+        "ln" => Expression::quotient(one(), arg),
+        "log10" => Expression::quotient(
+            one(),
+            Expression::product(arg, Expression::call("ln", Expression::number(10))),
+        ),
+        "sqrt" => Expression::quotient(
+            one(),
+            Expression::product(Expression::number(2), Expression::call("sqrt", arg)),
+        ),
+        "asin" | "acos" => {
+            let derivative = Expression::quotient(
+                one(),
+                Expression::call("sqrt", Expression::difference(one(), squared(arg))),
+            );
+            if name == "asin" {
+                derivative
+            } else {
+                Expression::negation(derivative)
+            }
+        }
+        "atan" => Expression::quotient(one(), Expression::sum(one(), squared(arg))),
+        "abs" => Expression::quotient(arg.clone(), Expression::call("abs", arg)),
+        _ => return Err(no_derivative(name)),
+    })
+}
+
+fn no_derivative(name: &str) -> CalcError {
+    CalcError::type_mismatch(format!("diff doesn't know the derivative of {}", name))
+}
+
+/// The result of arithmetic between two numbers, or the expression it came
+/// from if the arithmetic failed, like dividing by zero, so that the error
+/// comes up when the expression is evaluated instead.
+fn arithmetic(
+    lhs: Object,
+    rhs: Object,
+    operator: fn(Object, Object) -> Object,
+    unfolded: fn(Box<Expression>, Box<Expression>) -> Expression,
+) -> Expression {
+    match operator(lhs.clone(), rhs.clone()) {
+        Object::Error(_) => unfolded(
+            Box::new(Expression::Number(lhs)),
+            Box::new(Expression::Number(rhs)),
+        ),
+        result => Expression::Number(result),
+    }
+}
+
+/// How tightly a number binds when it is shown. `1/2` is shown like a
+/// division and `-2` like a negation, which is how they'd be read back in.
+fn number_precedence(number: &Object) -> u8 {
+    match *number {
+        Object::Rational(_) => PRODUCT,
+        Object::Complex(complex) if complex.re != 0.0 => SUM,
+        ref number if number.to_string().starts_with('-') => NEGATION,
+        _ => ATOM,
+    }
+}
+
+/// Shows `expression`, in parentheses if it binds less tightly than
+/// `precedence`.
+struct Operand<'a>(&'a Expression, u8);
+
+impl<'a> fmt::Display for Operand<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.precedence() < self.1 {
+            write!(f, "({})", self.0)
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expression::Number(ref number) => write!(f, "{}", number),
+            Expression::Symbol(ref name) => write!(f, "{}", name),
+            Expression::Neg(ref inner) => write!(f, "-{}", Operand(inner, NEGATION)),
+            // The right side of `-` and `/` needs parentheses when it is the
+            // same kind of operation, since `a - (b - c)` isn't `a - b - c`.
+            Expression::Add(ref lhs, ref rhs) => {
+                write!(f, "{} + {}", Operand(lhs, SUM), Operand(rhs, SUM))
+            }
+            Expression::Sub(ref lhs, ref rhs) => {
+                write!(f, "{} - {}", Operand(lhs, SUM), Operand(rhs, PRODUCT))
+            }
+            Expression::Mul(ref lhs, ref rhs) => {
+                write!(f, "{} * {}", Operand(lhs, PRODUCT), Operand(rhs, PRODUCT))
+            }
+            Expression::Div(ref lhs, ref rhs) => {
+                write!(f, "{} / {}", Operand(lhs, PRODUCT), Operand(rhs, NEGATION))
+            }
+            // The base of a power has to be a single value, but the exponent
+            // can be signed or another power.
+            Expression::Pow(ref base, ref exponent) => {
+                write!(f, "{}^{}", Operand(base, ATOM), Operand(exponent, NEGATION))
+            }
+            Expression::Call(ref name, ref args) => {
+                let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Symbolic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
@@ -0,0 +1,9 @@
+/// A function defined by the user, like `f(x, y) = x^2 + y`.
+///
+/// The body is kept as source text and only parsed and evaluated when the
+/// function is called, with the parameters bound to the arguments.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Function {
+    pub params: Vec<String>,
+    pub body: String,
+}
@@ -0,0 +1,502 @@
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{Signed, Zero};
+use std::{fmt,
+          ops::{Add, Mul, Neg}};
+
+use super::{CalcError, Object};
+
+/// How small a float can be, relative to the largest entry of a matrix, and
+/// still count as zero when choosing pivots. Anything smaller is rounding
+/// error left behind by elimination.
+const PIVOT_TOLERANCE: f64 = 1e-10;
+
+/// The most bits an exact entry of a matrix power may need before it is
+/// refused, the same limit as for exact powers of numbers.
+const MAX_BITS: u64 = 1 << 20;
+
+/// A rectangular grid of numbers, like `[[1, 2], [3, 4]]`. The entries are
+/// stored a row at a time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Matrix {
+    rows: usize,
+    columns: usize,
+    entries: Vec<Object>,
+}
+
+/// What is left of a matrix after it has been through `Matrix::reduce`.
+struct Reduced {
+    /// How many pivots were found, which is the rank of the part of the
+    /// matrix that pivots were chosen from.
+    rank: usize,
+    /// The determinant of that part, if it was square.
+    determinant: Object,
+}
+
+impl Matrix {
+    /// Makes a matrix out of a list of rows, if they are all lists of numbers
+    /// of the same, non-zero length.
+    pub fn from_rows(rows: &[Object]) -> Option<Matrix> {
+        let columns = match rows.first() {
+            Some(Object::List(first)) if !first.is_empty() => first.len(),
+            _ => return None,
+        };
+
+        let mut entries = Vec::with_capacity(rows.len() * columns);
+        for row in rows {
+            match *row {
+                Object::List(ref row) if row.len() == columns => {
+                    if !row.iter().all(is_number) {
+                        return None;
+                    }
+                    entries.extend(row.iter().cloned());
+                }
+                _ => return None,
+            }
+        }
+
+        Some(Matrix {
+            rows: rows.len(),
+            columns,
+            entries,
+        })
+    }
+
+    /// The `size` by `size` identity matrix.
+    pub fn identity(size: usize) -> Matrix {
+        Matrix::from_fn(size, size, |row, column| Object::Integer((row == column) as i64))
+    }
+
+    fn from_fn<F>(rows: usize, columns: usize, mut entry: F) -> Matrix
+    where
+        F: FnMut(usize, usize) -> Object,
+    {
+        let mut entries = Vec::with_capacity(rows * columns);
+        for row in 0..rows {
+            for column in 0..columns {
+                entries.push(entry(row, column));
+            }
+        }
+        Matrix {
+            rows,
+            columns,
+            entries,
+        }
+    }
+
+    /// Makes a matrix out of entries that may include errors, giving the
+    /// first error instead if there is one.
+    fn checked(rows: usize, columns: usize, entries: Vec<Object>) -> Object {
+        match entries.iter().position(|entry| matches!(*entry, Object::Error(_))) {
+            Some(position) => entries.into_iter().nth(position).unwrap(),
+            None => Object::Matrix(Matrix {
+                rows,
+                columns,
+                entries,
+            }),
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    fn get(&self, row: usize, column: usize) -> &Object {
+        &self.entries[row * self.columns + column]
+    }
+
+    fn is_square(&self) -> bool {
+        self.rows == self.columns
+    }
+
+    /// The size of the matrix, the way it is written in error messages.
+    fn size(&self) -> String {
+        format!("{} by {}", self.rows, self.columns)
+    }
+
+    /// The matrix as a list of rows, each of which is a list.
+    pub fn into_rows(self) -> Vec<Object> {
+        let columns = self.columns;
+        let mut entries = self.entries.into_iter();
+        (0..self.rows)
+            .map(|_| Object::List(entries.by_ref().take(columns).collect()))
+            .collect()
+    }
+
+    /// Applies `function` to every entry.
+    pub fn map<F: Fn(Object) -> Object>(self, function: F) -> Object {
+        let entries = self.entries.into_iter().map(function).collect();
+        Matrix::checked(self.rows, self.columns, entries)
+    }
+
+    /// Applies `function` to the matching entries of two matrices of the same
+    /// size, for adding and subtracting them.
+    pub fn zip_with<F>(self, verb: &str, rhs: Matrix, function: F) -> Object
+    where
+        F: Fn(Object, Object) -> Object,
+    {
+        if self.rows != rhs.rows || self.columns != rhs.columns {
+            return Object::Error(CalcError::type_mismatch(format!(
+                "can't {} a {} matrix and a {} matrix",
+                verb,
+                self.size(),
+                rhs.size()
+            )));
+        }
+        let entries = self.entries
+            .into_iter()
+            .zip(rhs.entries)
+            .map(|(lhs, rhs)| function(lhs, rhs))
+            .collect();
+        Matrix::checked(self.rows, self.columns, entries)
+    }
+
+    /// The matrix product of `self` and `rhs`.
+    pub fn product(&self, rhs: &Matrix) -> Object {
+        if self.columns != rhs.rows {
+            return Object::Error(CalcError::type_mismatch(format!(
+                "can't multiply a {} matrix by a {} matrix",
+                self.size(),
+                rhs.size()
+            )));
+        }
+        let product = Matrix::from_fn(self.rows, rhs.columns, |row, column| {
+            dot((0..self.columns).map(|k| (self.get(row, k), rhs.get(k, column))))
+        });
+        Matrix::checked(product.rows, product.columns, product.entries)
+    }
+
+    /// Multiplies the matrix by a list standing in for a column vector, so
+    /// that `A * x` is the left hand side of `A x = b`.
+    pub fn times_vector(&self, vector: &[Object]) -> Object {
+        if self.columns != vector.len() || !vector.iter().all(is_number) {
+            return Object::Error(CalcError::type_mismatch(format!(
+                "can't multiply a {} matrix by {}",
+                self.size(),
+                describe_vector(vector)
+            )));
+        }
+        (0..self.rows)
+            .map(|row| dot((0..self.columns).map(|k| (self.get(row, k), &vector[k]))))
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    /// Multiplies a list standing in for a row vector by the matrix.
+    pub fn vector_times(&self, vector: &[Object]) -> Object {
+        if self.rows != vector.len() || !vector.iter().all(is_number) {
+            return Object::Error(CalcError::type_mismatch(format!(
+                "can't multiply {} by a {} matrix",
+                describe_vector(vector),
+                self.size()
+            )));
+        }
+        (0..self.columns)
+            .map(|column| dot((0..self.rows).map(|k| (&vector[k], self.get(k, column)))))
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    /// Raises a square matrix to an integer power by repeated squaring. A
+    /// negative power is a power of the inverse.
+    pub fn pow(self, exponent: &Object) -> Object {
+        if !self.is_square() {
+            return Object::Error(not_square("be raised to a power"));
+        }
+        let exponent = match exponent.as_big_integer() {
+            Some(exponent) => exponent,
+            None => {
+                return Object::Error(CalcError::type_mismatch(
+                    "matrices can only be raised to integer powers",
+                ))
+            }
+        };
+
+        let base = if exponent.is_negative() {
+            match self.inverse() {
+                Object::Matrix(inverse) => inverse,
+                error => return error,
+            }
+        } else {
+            self
+        };
+        match base.power(exponent.abs()) {
+            Ok(power) => Object::Matrix(power),
+            Err(error) => error,
+        }
+    }
+
+    fn power(mut self, mut exponent: BigInt) -> Result<Matrix, Object> {
+        let mut result = Matrix::identity(self.rows);
+        let two = BigInt::from(2);
+        while !exponent.is_zero() {
+            if exponent.is_odd() {
+                result = into_matrix(result.product(&self))?.within_limits()?;
+            }
+            exponent /= &two;
+            if !exponent.is_zero() {
+                self = into_matrix(self.product(&self))?.within_limits()?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Makes sure no exact entry has grown too large to keep working with.
+    fn within_limits(self) -> Result<Matrix, Object> {
+        let too_large = self.entries.iter().any(|entry| match entry.as_rational() {
+            Some(ratio) => ratio.numer().bits().max(ratio.denom().bits()) > MAX_BITS,
+            None => false,
+        });
+        if too_large {
+            Err(Object::Error(CalcError::overflow(
+                "that number is too large to work out exactly",
+            )))
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// The matrix with its rows and columns swapped.
+    pub fn transpose(&self) -> Matrix {
+        Matrix::from_fn(self.columns, self.rows, |row, column| self.get(column, row).clone())
+    }
+
+    pub fn determinant(mut self) -> Object {
+        if !self.is_square() {
+            return Object::Error(not_square("have a determinant"));
+        }
+        let columns = self.columns;
+        match self.reduce(columns) {
+            Ok(reduced) => reduced.determinant,
+            Err(error) => Object::Error(error),
+        }
+    }
+
+    pub fn rank(mut self) -> Object {
+        let columns = self.columns;
+        match self.reduce(columns) {
+            Ok(reduced) => Object::Integer(reduced.rank as i64),
+            Err(error) => Object::Error(error),
+        }
+    }
+
+    /// The inverse of a square matrix, found by reducing the matrix next to
+    /// the identity until the identity is on the left.
+    pub fn inverse(self) -> Object {
+        if !self.is_square() {
+            return Object::Error(not_square("have an inverse"));
+        }
+        let size = self.rows;
+        let identity = Matrix::identity(size);
+        let mut augmented = Matrix::from_fn(size, 2 * size, |row, column| {
+            if column < size {
+                self.get(row, column).clone()
+            } else {
+                identity.get(row, column - size).clone()
+            }
+        });
+
+        match augmented.reduce(size) {
+            Ok(ref reduced) if reduced.rank < size => Object::Error(CalcError::domain(
+                "that matrix is singular, so it has no inverse",
+            )),
+            Ok(_) => Matrix::from_fn(size, size, |row, column| {
+                augmented.get(row, column + size).clone()
+            }).into(),
+            Err(error) => Object::Error(error),
+        }
+    }
+
+    /// Solves `A x = b` for `x`, where `A` is this matrix and `b` and the
+    /// solution are lists.
+    pub fn solve(&self, rhs: &[Object]) -> Object {
+        if !self.is_square() {
+            return Object::Error(not_square("be solved against"));
+        }
+        if self.rows != rhs.len() || !rhs.iter().all(is_number) {
+            return Object::Error(CalcError::type_mismatch(format!(
+                "a {} matrix has to be solved against a list of {} numbers",
+                self.size(),
+                self.rows
+            )));
+        }
+
+        let size = self.rows;
+        let mut augmented = Matrix::from_fn(size, size + 1, |row, column| {
+            if column < size {
+                self.get(row, column).clone()
+            } else {
+                rhs[row].clone()
+            }
+        });
+        match augmented.reduce(size) {
+            Ok(ref reduced) if reduced.rank < size => Object::Error(CalcError::domain(
+                "that matrix is singular, so there isn't exactly one solution",
+            )),
+            Ok(_) => (0..size)
+                .map(|row| augmented.get(row, size).clone())
+                .collect::<Vec<_>>()
+                .into(),
+            Err(error) => Object::Error(error),
+        }
+    }
+
+    /// Brings the matrix to reduced row echelon form by Gauss-Jordan
+    /// elimination, only choosing pivots from the first `columns` columns so
+    /// that anything to the right of them, like the identity when finding an
+    /// inverse, just follows along.
+    ///
+    /// Exact entries stay exact. Floats pick the largest pivot available to
+    /// keep rounding error down, and anything within `PIVOT_TOLERANCE` of
+    /// zero counts as zero.
+    fn reduce(&mut self, columns: usize) -> Result<Reduced, CalcError> {
+        let scale = self.entries
+            .iter()
+            .filter_map(|entry| entry.as_complex())
+            .map(|entry| entry.norm())
+            .fold(0.0, f64::max);
+        let mut determinant = Object::Integer(1);
+        let mut rank = 0;
+
+        for column in 0..columns {
+            if rank == self.rows {
+                break;
+            }
+            let pivot_row = (rank..self.rows)
+                .filter(|&row| !is_zero(self.get(row, column), scale))
+                .max_by(|&lhs, &rhs| {
+                    let lhs = magnitude(self.get(lhs, column));
+                    let rhs = magnitude(self.get(rhs, column));
+                    lhs.partial_cmp(&rhs).unwrap()
+                });
+            let pivot_row = match pivot_row {
+                Some(row) => row,
+                None => {
+                    determinant = Object::Integer(0);
+                    continue;
+                }
+            };
+            if pivot_row != rank {
+                self.swap_rows(pivot_row, rank);
+                determinant = check(-determinant)?;
+            }
+
+            // Scale the pivot row so the pivot is 1, then clear the rest of
+            // the column with it.
+            let pivot = self.get(rank, column).clone();
+            determinant = check(determinant * pivot.clone())?;
+            for entry in self.row_mut(rank) {
+                *entry = check(entry.clone() / pivot.clone())?;
+            }
+            for row in 0..self.rows {
+                let factor = self.get(row, column).clone();
+                if row == rank || is_zero(&factor, 0.0) {
+                    continue;
+                }
+                for k in 0..self.columns {
+                    let scaled = check(factor.clone() * self.get(rank, k).clone())?;
+                    let entry = &mut self.entries[row * self.columns + k];
+                    *entry = check(entry.clone() - scaled)?;
+                }
+            }
+            rank += 1;
+        }
+
+        if rank < columns {
+            determinant = Object::Integer(0);
+        }
+        Ok(Reduced { rank, determinant })
+    }
+
+    fn row_mut(&mut self, row: usize) -> &mut [Object] {
+        &mut self.entries[row * self.columns..(row + 1) * self.columns]
+    }
+
+    fn swap_rows(&mut self, first: usize, second: usize) {
+        for column in 0..self.columns {
+            self.entries.swap(first * self.columns + column, second * self.columns + column);
+        }
+    }
+}
+
+impl Neg for Matrix {
+    type Output = Object;
+    fn neg(self) -> Self::Output {
+        self.map(Neg::neg)
+    }
+}
+
+impl fmt::Display for Matrix {
+    /// Written as a list of rows, the same way it is typed in.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows: Vec<String> = self.entries
+            .chunks(self.columns)
+            .map(|row| {
+                let row: Vec<String> = row.iter().map(ToString::to_string).collect();
+                format!("[{}]", row.join(", "))
+            })
+            .collect();
+        write!(f, "[{}]", rows.join(", "))
+    }
+}
+
+impl From<Matrix> for Object {
+    fn from(matrix: Matrix) -> Object {
+        Object::Matrix(matrix)
+    }
+}
+
+fn is_number(object: &Object) -> bool {
+    object.as_complex().is_some()
+}
+
+/// Whether an entry counts as zero, allowing for rounding error in floats
+/// relative to `scale`.
+fn is_zero(entry: &Object, scale: f64) -> bool {
+    match entry.as_rational() {
+        Some(ratio) => ratio.is_zero(),
+        None => magnitude(entry) <= PIVOT_TOLERANCE * scale,
+    }
+}
+
+fn magnitude(entry: &Object) -> f64 {
+    entry.as_complex().map_or(0.0, |entry| entry.norm())
+}
+
+fn into_matrix(object: Object) -> Result<Matrix, Object> {
+    match object {
+        Object::Matrix(matrix) => Ok(matrix),
+        other => Err(other),
+    }
+}
+
+fn check(object: Object) -> Result<Object, CalcError> {
+    match object {
+        Object::Error(error) => Err(error),
+        object => Ok(object),
+    }
+}
+
+/// The sum of the products of pairs of entries.
+fn dot<'a, I: Iterator<Item = (&'a Object, &'a Object)>>(pairs: I) -> Object {
+    let mut total = Object::Integer(0);
+    for (lhs, rhs) in pairs {
+        total = total.add(lhs.clone().mul(rhs.clone()));
+        if let Object::Error(_) = total {
+            break;
+        }
+    }
+    total
+}
+
+fn describe_vector(vector: &[Object]) -> String {
+    if vector.iter().all(is_number) {
+        format!("a list of {}", vector.len())
+    } else {
+        "a list that isn't all numbers".to_string()
+    }
+}
+
+fn not_square(what: &str) -> CalcError {
+    CalcError::type_mismatch(format!("only square matrices can {}", what))
+}
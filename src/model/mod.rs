@@ -1,5 +1,17 @@
+mod error;
+mod expression;
+mod function;
+mod matrix;
 mod object;
+mod plot;
+mod quantity;
 mod state;
 
-pub use self::object::{Object, InfoType};
-pub use self::state::{State, StateRef};
+pub use self::error::{CalcError, Span};
+pub use self::expression::{Expression, Symbolic};
+pub use self::function::Function;
+pub use self::matrix::Matrix;
+pub use self::object::{Base, Comparison, Object, InfoType};
+pub use self::plot::{Plot, Series};
+pub use self::quantity::{Dimension, Quantity, Unit};
+pub use self::state::State;
@@ -1,32 +1,157 @@
-use std::ops::{Add, Div, Mul, Rem, Sub};
-use stdweb::{traits::*,
-             unstable::TryInto,
-             web::{document, HtmlElement}};
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+use std::{cmp::Ordering,
+          fmt,
+          ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Rem, Shl, Shr, Sub}};
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+use super::{CalcError, Matrix, Plot, Quantity, Symbolic};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum InfoType {
     About,
     Help,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A base that integers can be shown in with `to`, like `255 to hex`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Base {
+    Binary,
+    Octal,
+    Decimal,
+    Hexadecimal,
+}
+
+impl Base {
+    /// The base named after `to`, if there is one.
+    pub fn from_name(name: &str) -> Option<Base> {
+        match name {
+            "bin" => Some(Base::Binary),
+            "oct" => Some(Base::Octal),
+            "dec" => Some(Base::Decimal),
+            "hex" => Some(Base::Hexadecimal),
+            _ => None,
+        }
+    }
+
+    fn radix(self) -> u32 {
+        match self {
+            Base::Binary => 2,
+            Base::Octal => 8,
+            Base::Decimal => 10,
+            Base::Hexadecimal => 16,
+        }
+    }
+
+    /// The prefix that integer literals in this base are written with.
+    fn prefix(self) -> &'static str {
+        match self {
+            Base::Binary => "0b",
+            Base::Octal => "0o",
+            Base::Decimal => "",
+            Base::Hexadecimal => "0x",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Object {
     Integer(i64),
+    BigInteger(BigInt),
+    Rational(BigRational),
     Float(f64),
-    Error(String),
+    Complex(Complex64),
+    Quantity(Quantity),
+    /// An integer to be shown in a base other than ten. It behaves exactly
+    /// like the integer in calculations.
+    InBase(BigInt, Base),
+    Boolean(bool),
+    /// A list of values, like `[1, 2, 3]`. Arithmetic on a list applies to
+    /// each of its elements.
+    List(Vec<Object>),
+    /// A list of rows of numbers that are all the same length, like
+    /// `[[1, 2], [3, 4]]`. Multiplying matrices gives their matrix product.
+    Matrix(Matrix),
+    /// An expression that can't be evaluated until its variable has a value,
+    /// like the derivative that `diff(x^2, x)` gives.
+    Symbolic(Box<Symbolic>),
+    /// An expression sampled over a range, like `plot(sin(x), x, 0, 2pi)`,
+    /// which is shown as a chart rather than used in calculations.
+    Plot(Plot),
+    Error(CalcError),
     Info(InfoType),
     Nil,
 }
 
+/// One of the comparison operators, like `==` or `<`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// Whether two things that compare as `ordering` pass. `None` means they
+    /// are different but can't be ordered, like two complex numbers.
+    fn holds(self, ordering: Option<Ordering>) -> Result<bool, CalcError> {
+        match (self, ordering) {
+            (Comparison::Equal, ordering) => Ok(ordering == Some(Ordering::Equal)),
+            (Comparison::NotEqual, ordering) => Ok(ordering != Some(Ordering::Equal)),
+            (_, None) => Err(CalcError::type_mismatch("complex numbers can't be ordered")),
+            (Comparison::Less, Some(ordering)) => Ok(ordering == Ordering::Less),
+            (Comparison::LessOrEqual, Some(ordering)) => Ok(ordering != Ordering::Greater),
+            (Comparison::Greater, Some(ordering)) => Ok(ordering == Ordering::Greater),
+            (Comparison::GreaterOrEqual, Some(ordering)) => Ok(ordering != Ordering::Less),
+        }
+    }
+}
+
+/// How far apart two floats can be, relative to the larger of them, and
+/// still be equal. Anything closer than that is rounding error, so that
+/// `0.1 + 0.2 == 0.3`.
+const RELATIVE_TOLERANCE: f64 = 1e-9;
+
+/// How far a float can be from exactly zero and still be equal to it, so
+/// that `sin(pi) == 0`. Nothing is ever less or greater than zero by less
+/// than this, though, so `1e-13 > 0`.
+const ABSOLUTE_TOLERANCE: f64 = 1e-12;
+
+/// Whether two floats are the same but for rounding error. Only `equality`
+/// lets something tiny count as zero, since it has no size to be relative to.
+fn close(lhs: f64, rhs: f64, equality: bool) -> bool {
+    let difference = (lhs - rhs).abs();
+    if lhs == 0.0 || rhs == 0.0 {
+        equality && difference <= ABSOLUTE_TOLERANCE
+    } else {
+        difference <= RELATIVE_TOLERANCE * lhs.abs().max(rhs.abs())
+    }
+}
+
 impl Add for Object {
     type Output = Object;
     fn add(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Object::Integer(lhs), Object::Integer(rhs)) => Object::Integer(lhs + rhs),
-            (Object::Float(lhs), Object::Float(rhs)) => Object::Float(lhs + rhs),
-            (Object::Integer(lhs), Object::Float(rhs))
-            | (Object::Float(rhs), Object::Integer(lhs)) => Object::Float(lhs as f64 + rhs as f64),
-            _ => Object::Error("that operation isn't supported".to_string()),
+        if has_matrix(&self, &rhs) {
+            return combine_matrices(self, rhs, "add", Add::add);
+        }
+        if has_list(&self, &rhs) {
+            return broadcast(self, rhs, Add::add);
+        }
+        match promote(self, rhs) {
+            Some(Promoted::Integer(lhs, rhs)) => match lhs.checked_add(rhs) {
+                Some(sum) => Object::Integer(sum),
+                None => (BigInt::from(lhs) + rhs).into(),
+            },
+            Some(Promoted::BigInteger(lhs, rhs)) => (lhs + rhs).into(),
+            Some(Promoted::Rational(lhs, rhs)) => (lhs + rhs).into(),
+            Some(Promoted::Float(lhs, rhs)) => (lhs + rhs).into(),
+            Some(Promoted::Complex(lhs, rhs)) => (lhs + rhs).into(),
+            Some(Promoted::Quantity(lhs, rhs)) => lhs.add(rhs),
+            None => Object::Error(CalcError::unsupported()),
         }
     }
 }
@@ -34,12 +159,23 @@ impl Add for Object {
 impl Sub for Object {
     type Output = Object;
     fn sub(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Object::Integer(lhs), Object::Integer(rhs)) => Object::Integer(lhs - rhs),
-            (Object::Float(lhs), Object::Float(rhs)) => Object::Float(lhs - rhs),
-            (Object::Integer(lhs), Object::Float(rhs)) => Object::Float(lhs as f64 - rhs as f64),
-            (Object::Float(lhs), Object::Integer(rhs)) => Object::Float(lhs as f64 - rhs as f64),
-            _ => Object::Error("that operation isn't supported".to_string()),
+        if has_matrix(&self, &rhs) {
+            return combine_matrices(self, rhs, "subtract", Sub::sub);
+        }
+        if has_list(&self, &rhs) {
+            return broadcast(self, rhs, Sub::sub);
+        }
+        match promote(self, rhs) {
+            Some(Promoted::Integer(lhs, rhs)) => match lhs.checked_sub(rhs) {
+                Some(difference) => Object::Integer(difference),
+                None => (BigInt::from(lhs) - rhs).into(),
+            },
+            Some(Promoted::BigInteger(lhs, rhs)) => (lhs - rhs).into(),
+            Some(Promoted::Rational(lhs, rhs)) => (lhs - rhs).into(),
+            Some(Promoted::Float(lhs, rhs)) => (lhs - rhs).into(),
+            Some(Promoted::Complex(lhs, rhs)) => (lhs - rhs).into(),
+            Some(Promoted::Quantity(lhs, rhs)) => lhs.sub(rhs),
+            None => Object::Error(CalcError::unsupported()),
         }
     }
 }
@@ -47,12 +183,23 @@ impl Sub for Object {
 impl Mul for Object {
     type Output = Object;
     fn mul(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Object::Integer(lhs), Object::Integer(rhs)) => Object::Integer(lhs * rhs),
-            (Object::Float(lhs), Object::Float(rhs)) => Object::Float(lhs * rhs),
-            (Object::Integer(lhs), Object::Float(rhs))
-            | (Object::Float(rhs), Object::Integer(lhs)) => Object::Float(lhs as f64 * rhs as f64),
-            _ => Object::Error("that operation isn't supported".to_string()),
+        if has_matrix(&self, &rhs) {
+            return matrix_product(self, rhs);
+        }
+        if has_list(&self, &rhs) {
+            return broadcast(self, rhs, Mul::mul);
+        }
+        match promote(self, rhs) {
+            Some(Promoted::Integer(lhs, rhs)) => match lhs.checked_mul(rhs) {
+                Some(product) => Object::Integer(product),
+                None => (BigInt::from(lhs) * rhs).into(),
+            },
+            Some(Promoted::BigInteger(lhs, rhs)) => (lhs * rhs).into(),
+            Some(Promoted::Rational(lhs, rhs)) => (lhs * rhs).into(),
+            Some(Promoted::Float(lhs, rhs)) => (lhs * rhs).into(),
+            Some(Promoted::Complex(lhs, rhs)) => (lhs * rhs).into(),
+            Some(Promoted::Quantity(lhs, rhs)) => lhs.mul(rhs),
+            None => Object::Error(CalcError::unsupported()),
         }
     }
 }
@@ -60,12 +207,27 @@ impl Mul for Object {
 impl Div for Object {
     type Output = Object;
     fn div(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Object::Integer(lhs), Object::Integer(rhs)) => Object::Integer(lhs / rhs),
-            (Object::Float(lhs), Object::Float(rhs)) => Object::Float(lhs / rhs),
-            (Object::Integer(lhs), Object::Float(rhs)) => Object::Float(lhs as f64 / rhs as f64),
-            (Object::Float(lhs), Object::Integer(rhs)) => Object::Float(lhs as f64 / rhs as f64),
-            _ => Object::Error("that operation isn't supported".to_string()),
+        if has_matrix(&self, &rhs) {
+            return matrix_quotient(self, rhs);
+        }
+        if has_list(&self, &rhs) {
+            return broadcast(self, rhs, Div::div);
+        }
+        match promote(self, rhs) {
+            // Dividing two integers is exact, so `7 / 2` is `7/2` rather than
+            // `3`.
+            Some(Promoted::Integer(lhs, rhs)) if rhs != 0 => {
+                BigRational::new(lhs.into(), rhs.into()).into()
+            }
+            Some(Promoted::BigInteger(lhs, rhs)) if !rhs.is_zero() => {
+                BigRational::new(lhs, rhs).into()
+            }
+            Some(Promoted::Rational(lhs, rhs)) if !rhs.is_zero() => (lhs / rhs).into(),
+            Some(Promoted::Float(lhs, rhs)) if rhs != 0.0 => (lhs / rhs).into(),
+            Some(Promoted::Complex(lhs, rhs)) if !rhs.is_zero() => (lhs / rhs).into(),
+            Some(Promoted::Quantity(lhs, rhs)) => lhs.div(rhs),
+            Some(_) => Object::Error(CalcError::DivisionByZero { span: None }),
+            None => Object::Error(CalcError::unsupported()),
         }
     }
 }
@@ -73,150 +235,644 @@ impl Div for Object {
 impl Rem for Object {
     type Output = Object;
     fn rem(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Object::Integer(lhs), Object::Integer(rhs)) => Object::Integer(lhs % rhs),
-            (Object::Float(lhs), Object::Float(rhs)) => Object::Float(lhs % rhs),
-            (Object::Integer(lhs), Object::Float(rhs)) => Object::Float(lhs as f64 % rhs as f64),
-            (Object::Float(lhs), Object::Integer(rhs)) => Object::Float(lhs as f64 % rhs as f64),
-            _ => Object::Error("that operation isn't supported".to_string()),
+        if has_matrix(&self, &rhs) {
+            return Object::Error(CalcError::unsupported());
+        }
+        if has_list(&self, &rhs) {
+            return broadcast(self, rhs, Rem::rem);
+        }
+        match promote(self, rhs) {
+            // `checked_rem` only fails for a zero divisor or `i64::MIN % -1`.
+            Some(Promoted::Integer(lhs, rhs)) if rhs != 0 => match lhs.checked_rem(rhs) {
+                Some(remainder) => Object::Integer(remainder),
+                None => (BigInt::from(lhs) % rhs).into(),
+            },
+            Some(Promoted::BigInteger(lhs, rhs)) if !rhs.is_zero() => (lhs % rhs).into(),
+            Some(Promoted::Rational(lhs, rhs)) if !rhs.is_zero() => (lhs % rhs).into(),
+            Some(Promoted::Float(lhs, rhs)) if rhs != 0.0 => (lhs % rhs).into(),
+            Some(Promoted::Complex(_, _)) => Object::Error(CalcError::type_mismatch(
+                "remainders aren't defined for complex numbers",
+            )),
+            Some(Promoted::Quantity(lhs, rhs)) => lhs.rem(rhs),
+            Some(_) => Object::Error(CalcError::DivisionByZero { span: None }),
+            None => Object::Error(CalcError::unsupported()),
+        }
+    }
+}
+
+impl Neg for Object {
+    type Output = Object;
+    fn neg(self) -> Self::Output {
+        match self {
+            Object::Integer(int) => match int.checked_neg() {
+                Some(negated) => Object::Integer(negated),
+                None => (-BigInt::from(int)).into(),
+            },
+            Object::BigInteger(int) => (-int).into(),
+            Object::Rational(ratio) => Object::Rational(-ratio),
+            Object::Float(float) => Object::Float(-float),
+            Object::Complex(complex) => Object::Complex(-complex),
+            Object::Quantity(quantity) => quantity.neg(),
+            Object::InBase(int, _) => (-int).into(),
+            Object::List(items) => items.into_iter().map(Neg::neg).collect::<Vec<_>>().into(),
+            Object::Matrix(matrix) => -matrix,
+            _ => Object::Error(CalcError::unsupported()),
+        }
+    }
+}
+
+// Bitwise operators only work on integers. Integers shown in another base
+// stay in it, so `0xF0 | 1` is `0xF1` if `0xF0` was shown in hex.
+macro_rules! bitwise_operators {
+    ( $( $trait:ident::$name:ident ),* ) => {
+        $(
+            impl $trait for Object {
+                type Output = Object;
+                fn $name(self, rhs: Self) -> Self::Output {
+                    let base = self.base().or_else(|| rhs.base());
+                    match (self.as_big_integer(), rhs.as_big_integer()) {
+                        (Some(lhs), Some(rhs)) => Object::from(lhs.$name(rhs)).keep_base(base),
+                        _ => Object::Error(not_integers()),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+bitwise_operators!(BitAnd::bitand, BitOr::bitor, BitXor::bitxor);
+
+impl Not for Object {
+    type Output = Object;
+    fn not(self) -> Self::Output {
+        let base = self.base();
+        match self.as_big_integer() {
+            Some(int) => Object::from(!int).keep_base(base),
+            None => Object::Error(not_integers()),
+        }
+    }
+}
+
+impl Shl for Object {
+    type Output = Object;
+    fn shl(self, rhs: Self) -> Self::Output {
+        let base = self.base();
+        match (self.as_big_integer(), shift_amount(&rhs)) {
+            (Some(lhs), Ok(rhs)) => Object::from(lhs << rhs).keep_base(base),
+            (None, _) => Object::Error(not_integers()),
+            (_, Err(error)) => Object::Error(error),
+        }
+    }
+}
+
+impl Shr for Object {
+    type Output = Object;
+    fn shr(self, rhs: Self) -> Self::Output {
+        let base = self.base();
+        match (self.as_big_integer(), shift_amount(&rhs)) {
+            (Some(lhs), Ok(rhs)) => Object::from(lhs >> rhs).keep_base(base),
+            (None, _) => Object::Error(not_integers()),
+            (_, Err(error)) => Object::Error(error),
         }
     }
 }
 
+fn not_integers() -> CalcError {
+    CalcError::type_mismatch("bitwise operators can only be applied to integers")
+}
+
+/// How far to shift by, which has to be a small enough non-negative integer.
+fn shift_amount(amount: &Object) -> Result<u64, CalcError> {
+    match amount.as_big_integer() {
+        Some(ref amount) if amount.is_negative() => {
+            Err(CalcError::domain("can't shift by a negative amount"))
+        }
+        Some(amount) => match amount.to_u64() {
+            Some(amount) if amount <= MAX_BITS => Ok(amount),
+            _ => Err(CalcError::overflow("that shift is too large")),
+        },
+        None => Err(not_integers()),
+    }
+}
+
 impl Object {
     pub fn pow(self, rhs: Self) -> Self {
+        if has_matrix(&self, &rhs) {
+            return match (self, rhs) {
+                (Object::Matrix(matrix), rhs) => matrix.pow(&rhs),
+                _ => Object::Error(CalcError::type_mismatch("matrices can't be used as exponents")),
+            };
+        }
+        if has_list(&self, &rhs) {
+            return broadcast(self, rhs, Object::pow);
+        }
         match (self, rhs) {
-            (Object::Integer(lhs), Object::Integer(rhs)) => Object::Integer(lhs.pow(rhs as u32)),
-            (Object::Float(lhs), Object::Float(rhs)) => Object::Float(lhs.powf(rhs)),
-            (Object::Integer(lhs), Object::Float(rhs)) => Object::Float((lhs as f64).powf(rhs)),
-            (Object::Float(lhs), Object::Integer(rhs)) => Object::Float(lhs.powi(rhs as i32)),
-            _ => Object::Error("that operation isn't supported".to_string()),
+            (Object::Quantity(lhs), rhs) => lhs.pow(rhs),
+            (_, Object::Quantity(_)) => Object::Error(CalcError::units("exponents can't have units")),
+            (Object::Integer(lhs), Object::Integer(rhs)) if fits_u32(rhs) => {
+                match lhs.checked_pow(rhs as u32) {
+                    Some(power) => Object::Integer(power),
+                    None => exact_pow(BigRational::from_integer(lhs.into()), rhs),
+                }
+            }
+            // A negative exponent makes the result a fraction, so `2^-2` is
+            // `1/4`.
+            (ref lhs, Object::Integer(rhs)) if lhs.is_exact() => {
+                exact_pow(lhs.as_rational().unwrap(), rhs)
+            }
+            (Object::Float(lhs), Object::Integer(rhs)) if fits_i32(rhs) => {
+                lhs.powi(rhs as i32).into()
+            }
+            // Anything else, like a fractional exponent, can't be exact.
+            (lhs, rhs) => match (lhs.as_float(), rhs.as_float()) {
+                // A negative number has no real roots of its own, so
+                // `(-8)^(1/3)` is the principal complex root instead.
+                (Some(lhs), Some(rhs)) if lhs < 0.0 && rhs.fract() != 0.0 => {
+                    Complex64::new(lhs, 0.0).powf(rhs).into()
+                }
+                (Some(lhs), Some(rhs)) => lhs.powf(rhs).into(),
+                _ => match (lhs.as_complex(), rhs.as_complex()) {
+                    (Some(lhs), Some(rhs)) => complex_pow(lhs, rhs),
+                    _ => Object::Error(CalcError::unsupported()),
+                },
+            },
         }
     }
 
-    pub fn display(self) -> Option<HtmlElement> {
-        // A macro to create `p` elements.
-        macro_rules! new_text_node {
-            ($text:expr) => {{
-                let display: HtmlElement = document().create_element("p").unwrap().try_into().unwrap();
-                display.append_child(&document().create_text_node($text));
-                display
-            }};
+    /// The element of a list at `index`, counting from 0. Negative indices
+    /// count back from the end, so `v[-1]` is the last element. A matrix
+    /// gives one of its rows, as a list.
+    pub fn index(self, index: Object) -> Object {
+        let (items, whole) = match self {
+            Object::List(items) => {
+                let whole = format!("a list of {}", items.len());
+                (items, whole)
+            }
+            Object::Matrix(matrix) => {
+                let rows = matrix.rows();
+                let plural = if rows == 1 { "" } else { "s" };
+                let whole = format!("a matrix with {} row{}", rows, plural);
+                (matrix.into_rows(), whole)
+            }
+            _ => {
+                return Object::Error(CalcError::type_mismatch(
+                    "only lists and matrices can be indexed",
+                ))
+            }
+        };
+        let index = match index.as_big_integer() {
+            Some(index) => index,
+            None => {
+                return Object::Error(CalcError::type_mismatch("list indices have to be integers"))
+            }
+        };
+
+        // An index too big for an `i64` is out of range of any list.
+        let len = items.len() as i64;
+        let position = index.to_i64().map(|index| if index < 0 { index + len } else { index });
+        match position {
+            Some(position) if position >= 0 && position < len => {
+                items.into_iter().nth(position as usize).unwrap()
+            }
+            _ => Object::Error(CalcError::domain(format!(
+                "index {} is out of range for {}",
+                index, whole
+            ))),
+        }
+    }
+
+    /// Shows an integer in `base` from now on.
+    pub fn in_base(self, base: Base) -> Object {
+        match self.as_big_integer() {
+            Some(int) if base == Base::Decimal => int.into(),
+            Some(int) => Object::InBase(int, base),
+            None => Object::Error(CalcError::type_mismatch(
+                "only integers can be shown in another base",
+            )),
         }
+    }
 
-        // A macro to create links with specified text and location.
-        macro_rules! new_link_node {
-            ($href:expr, $text:expr) => {{
-                let link: HtmlElement = document().create_element("a").unwrap().try_into().unwrap();
-                link.append_child(&document().create_text_node($text));
-                link.set_attribute("href", $href).unwrap();
-                link
-            }};
+    fn base(&self) -> Option<Base> {
+        match *self {
+            Object::InBase(_, base) => Some(base),
+            _ => None,
         }
+    }
 
+    fn keep_base(self, base: Option<Base>) -> Object {
+        match base {
+            Some(base) => self.in_base(base),
+            None => self,
+        }
+    }
+
+    /// The value of a number as a float, or `None` if the object isn't a
+    /// number.
+    pub fn as_float(&self) -> Option<f64> {
+        match *self {
+            Object::Integer(int) => Some(int as f64),
+            Object::BigInteger(ref int) | Object::InBase(ref int, _) => int.to_f64(),
+            Object::Rational(ref ratio) => ratio.to_f64(),
+            Object::Float(float) => Some(float),
+            _ => None,
+        }
+    }
+
+    /// The value of a number as a complex number, or `None` if the object
+    /// isn't a number.
+    pub fn as_complex(&self) -> Option<Complex64> {
+        match *self {
+            Object::Complex(complex) => Some(complex),
+            _ => self.as_float().map(|float| Complex64::new(float, 0.0)),
+        }
+    }
+
+    /// Compares two objects with `comparison`, giving a boolean. Exact numbers
+    /// are compared exactly, but floats only have to be within rounding error
+    /// of each other to be equal.
+    pub fn compare_with(self, comparison: Comparison, rhs: Object) -> Object {
+        let ordering = match (self, rhs) {
+            (Object::Boolean(lhs), Object::Boolean(rhs)) => match comparison {
+                Comparison::Equal | Comparison::NotEqual => Ok(lhs.partial_cmp(&rhs)),
+                _ => Err(CalcError::type_mismatch(
+                    "true and false can only be compared with == and !=",
+                )),
+            },
+            (Object::Boolean(_), _) | (_, Object::Boolean(_)) => Err(CalcError::type_mismatch(
+                "true and false can't be compared with numbers",
+            )),
+            (lhs, rhs) => {
+                let equality = matches!(comparison, Comparison::Equal | Comparison::NotEqual);
+                tolerant_compare(lhs, rhs, equality)
+            }
+        };
+
+        match ordering.and_then(|ordering| comparison.holds(ordering)) {
+            Ok(result) => Object::Boolean(result),
+            Err(error) => Object::Error(error),
+        }
+    }
+
+    /// Whether the object counts as true in a conditional, which `true` and
+    /// any number other than zero do. Returns `None` for anything else.
+    pub fn truth(&self) -> Option<bool> {
+        match *self {
+            Object::Boolean(boolean) => Some(boolean),
+            ref number => number.as_complex().map(|complex| !complex.is_zero()),
+        }
+    }
+
+    /// `and` of two booleans.
+    pub fn and(self, rhs: Object) -> Object {
+        match (self, rhs) {
+            (Object::Boolean(lhs), Object::Boolean(rhs)) => Object::Boolean(lhs && rhs),
+            _ => Object::Error(not_booleans("and")),
+        }
+    }
+
+    /// `or` of two booleans.
+    pub fn or(self, rhs: Object) -> Object {
+        match (self, rhs) {
+            (Object::Boolean(lhs), Object::Boolean(rhs)) => Object::Boolean(lhs || rhs),
+            _ => Object::Error(not_booleans("or")),
+        }
+    }
+
+    /// `not` of a boolean. Unlike `~`, this doesn't work on integers.
+    pub fn logical_not(self) -> Object {
         match self {
-            Object::Integer(int) => Some(new_text_node!(&int.to_string())),
-            Object::Float(float) => Some(new_text_node!(&float.to_string())),
-            Object::Error(string) => {
-                let display = new_text_node!(&string);
-                display.class_list().add("error").unwrap();
-                Some(display)
+            Object::Boolean(boolean) => Object::Boolean(!boolean),
+            _ => Object::Error(not_booleans("not")),
+        }
+    }
+
+    /// Compares two numbers, or returns `None` if either object isn't a
+    /// number, one is complex (or one is `NaN`).
+    pub fn compare(&self, other: &Object) -> Option<Ordering> {
+        match promote(self.clone(), other.clone())? {
+            Promoted::Integer(lhs, rhs) => lhs.partial_cmp(&rhs),
+            Promoted::BigInteger(lhs, rhs) => lhs.partial_cmp(&rhs),
+            Promoted::Rational(lhs, rhs) => lhs.partial_cmp(&rhs),
+            Promoted::Float(lhs, rhs) => lhs.partial_cmp(&rhs),
+            Promoted::Complex(_, _) => None,
+            Promoted::Quantity(lhs, rhs) => lhs.compare(&rhs),
+        }
+    }
+
+    /// Whether the object is a number that is stored exactly, i.e. an integer
+    /// or a rational.
+    pub(crate) fn is_exact(&self) -> bool {
+        matches!(
+            *self,
+            Object::Integer(_) | Object::BigInteger(_) | Object::Rational(_) | Object::InBase(..)
+        )
+    }
+
+    pub(crate) fn as_big_integer(&self) -> Option<BigInt> {
+        match *self {
+            Object::Integer(int) => Some(int.into()),
+            Object::BigInteger(ref int) | Object::InBase(ref int, _) => Some(int.clone()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_rational(&self) -> Option<BigRational> {
+        match *self {
+            Object::Rational(ref ratio) => Some(ratio.clone()),
+            _ => self.as_big_integer().map(BigRational::from_integer),
+        }
+    }
+}
+
+fn not_booleans(operator: &str) -> CalcError {
+    CalcError::type_mismatch(format!("{} can only be applied to true or false", operator))
+}
+
+/// Compares two numbers, allowing for rounding error if either is a float,
+/// and for a float being zero if `equality` is all that matters. Complex
+/// numbers are only ever equal or not, so they give `None` when they're
+/// different.
+fn tolerant_compare(
+    lhs: Object,
+    rhs: Object,
+    equality: bool,
+) -> Result<Option<Ordering>, CalcError> {
+    match promote(lhs, rhs) {
+        Some(Promoted::Integer(lhs, rhs)) => Ok(Some(lhs.cmp(&rhs))),
+        Some(Promoted::BigInteger(lhs, rhs)) => Ok(Some(lhs.cmp(&rhs))),
+        Some(Promoted::Rational(lhs, rhs)) => Ok(Some(lhs.cmp(&rhs))),
+        Some(Promoted::Float(lhs, rhs)) if close(lhs, rhs, equality) => Ok(Some(Ordering::Equal)),
+        Some(Promoted::Float(lhs, rhs)) => Ok(lhs.partial_cmp(&rhs)),
+        Some(Promoted::Complex(lhs, rhs))
+            if close(lhs.re, rhs.re, equality) && close(lhs.im, rhs.im, equality) =>
+        {
+            Ok(Some(Ordering::Equal))
+        }
+        Some(Promoted::Complex(_, _)) => Ok(None),
+        Some(Promoted::Quantity(lhs, rhs)) => {
+            let (lhs, rhs) = lhs.values("compare", rhs)?;
+            tolerant_compare(lhs, rhs, equality)
+        }
+        None => Err(CalcError::unsupported()),
+    }
+}
+
+fn has_matrix(lhs: &Object, rhs: &Object) -> bool {
+    matches!((lhs, rhs), (&Object::Matrix(_), _) | (_, &Object::Matrix(_)))
+}
+
+/// Adds or subtracts two matrices of the same size, entry by entry.
+fn combine_matrices<F: Fn(Object, Object) -> Object>(
+    lhs: Object,
+    rhs: Object,
+    verb: &str,
+    operator: F,
+) -> Object {
+    match (lhs, rhs) {
+        (Object::Matrix(lhs), Object::Matrix(rhs)) => lhs.zip_with(verb, rhs, operator),
+        _ => Object::Error(CalcError::type_mismatch(format!(
+            "can only {} a matrix and another matrix",
+            verb
+        ))),
+    }
+}
+
+/// Multiplies two matrices, a matrix and a list standing in for a vector, or
+/// a matrix and a number.
+fn matrix_product(lhs: Object, rhs: Object) -> Object {
+    match (lhs, rhs) {
+        (Object::Matrix(lhs), Object::Matrix(rhs)) => lhs.product(&rhs),
+        (Object::Matrix(lhs), Object::List(rhs)) => lhs.times_vector(&rhs),
+        (Object::List(lhs), Object::Matrix(rhs)) => rhs.vector_times(&lhs),
+        (Object::Matrix(matrix), number) | (number, Object::Matrix(matrix)) => {
+            scale(matrix, number, Mul::mul)
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Divides a matrix by a number. Nothing can be divided by a matrix, since
+/// which side its inverse goes on matters.
+fn matrix_quotient(lhs: Object, rhs: Object) -> Object {
+    match (lhs, rhs) {
+        (Object::Matrix(matrix), number) => scale(matrix, number, Div::div),
+        _ => Object::Error(CalcError::type_mismatch(
+            "can't divide by a matrix, but you can multiply by its inverse",
+        )),
+    }
+}
+
+/// Multiplies or divides every entry of a matrix by a number.
+fn scale<F: Fn(Object, Object) -> Object>(matrix: Matrix, by: Object, operator: F) -> Object {
+    match by {
+        Object::Quantity(_) => Object::Error(CalcError::units("matrices can't have units")),
+        _ if by.as_complex().is_some() => matrix.map(|entry| operator(entry, by.clone())),
+        _ => Object::Error(CalcError::unsupported()),
+    }
+}
+
+fn has_list(lhs: &Object, rhs: &Object) -> bool {
+    matches!((lhs, rhs), (&Object::List(_), _) | (_, &Object::List(_)))
+}
+
+/// Applies `operator` between each element of a list and a single value, or
+/// between the matching elements of two lists of the same length, so that
+/// `[1, 2] * 3` is `[3, 6]` and `[1, 2] + [3, 4]` is `[4, 6]`.
+fn broadcast<F: Fn(Object, Object) -> Object>(lhs: Object, rhs: Object, operator: F) -> Object {
+    let results: Vec<Object> = match (lhs, rhs) {
+        (Object::List(lhs), Object::List(rhs)) => {
+            if lhs.len() != rhs.len() {
+                return Object::Error(CalcError::type_mismatch(format!(
+                    "can't combine a list of {} with a list of {}",
+                    lhs.len(),
+                    rhs.len()
+                )));
             }
-            Object::Info(InfoType::About) => {
-                let container: HtmlElement = document()
-                    .create_element("div")
-                    .unwrap()
-                    .try_into()
-                    .unwrap();
-                let display1 = new_text_node!(
-                    "This is a REPL calculator running on the web. It was made with the "
-                );
-                display1.append_child(&new_link_node!(
-                    "https://www.rust-lang.org",
-                    "Rust programming language"
-                ));
-                display1.append_child(&document().create_text_node(" and "));
-                display1.append_child(&new_link_node!("https://github.com/koute/stdweb", "stdweb"));
-                display1.append_child(&document().create_text_node(", a library for constructing client side web apps in Rust. It was compiled to "));
-                display1.append_child(&new_link_node!(
-                    "https://developer.mozilla.org/en-US/docs/WebAssembly",
-                    "WebAssembly"
-                ));
-                display1.append_child(&document().create_text_node(" and then included in an html file to run on the web. To see the source code, check it out on "));
-                display1.append_child(&new_link_node!(
-                    "https://github.com/cgm616/calc_rs",
-                    "Github."
-                ));
-                let line_break: HtmlElement =
-                    document().create_element("br").unwrap().try_into().unwrap();
-                let display2 = new_text_node!("Try running `help()` for more info.");
-
-                container.append_child(&display1);
-                container.append_child(&line_break);
-                container.append_child(&display2);
-                container.class_list().add("info").unwrap();
-                Some(container)
+            lhs.into_iter().zip(rhs).map(|(lhs, rhs)| operator(lhs, rhs)).collect()
+        }
+        (Object::List(lhs), rhs) => lhs.into_iter().map(|lhs| operator(lhs, rhs.clone())).collect(),
+        (lhs, Object::List(rhs)) => rhs.into_iter().map(|rhs| operator(lhs.clone(), rhs)).collect(),
+        _ => unreachable!(),
+    };
+    results.into()
+}
+
+/// The most bits the numerator or denominator of an exact power may need
+/// before it is refused, so that something like `2^(2^40)` can't lock up the
+/// calculator.
+const MAX_BITS: u64 = 1 << 20;
+
+fn exact_pow(base: BigRational, exponent: i64) -> Object {
+    if base.is_zero() && exponent < 0 {
+        return Object::Error(CalcError::DivisionByZero { span: None });
+    }
+
+    let bits = base.numer().bits().max(base.denom().bits());
+    if bits > 1 && bits.saturating_mul(exponent.unsigned_abs()) > MAX_BITS {
+        return Object::Error(CalcError::overflow(
+            "that number is too large to work out exactly",
+        ));
+    }
+
+    // Only 0, 1 and -1 can get this far with an exponent that doesn't fit in
+    // an `i32`, and for them all that matters is whether it is odd.
+    let exponent = if fits_i32(exponent) {
+        exponent as i32
+    } else {
+        2 + (exponent % 2) as i32
+    };
+    base.pow(exponent).into()
+}
+
+fn complex_pow(base: Complex64, exponent: Complex64) -> Object {
+    // `powc` goes through the logarithm of the base, which doesn't exist for
+    // zero.
+    if base.is_zero() && !exponent.is_zero() {
+        return if exponent.re > 0.0 {
+            Object::Integer(0)
+        } else {
+            Object::Error(CalcError::DivisionByZero { span: None })
+        };
+    }
+
+    // Whole exponents are worked out by repeated multiplication, which keeps
+    // results like `(1+i)^2` free of rounding errors.
+    let whole = exponent.re.fract() == 0.0 && exponent.re.abs() <= f64::from(i32::MAX);
+    if exponent.im == 0.0 && whole {
+        base.powi(exponent.re as i32).into()
+    } else {
+        base.powc(exponent).into()
+    }
+}
+
+fn fits_u32(int: i64) -> bool {
+    int >= 0 && int <= i64::from(u32::MAX)
+}
+
+fn fits_i32(int: i64) -> bool {
+    int >= i64::from(i32::MIN) && int <= i64::from(i32::MAX)
+}
+
+/// Two numbers that have been brought to a common type before an arithmetic
+/// operation.
+enum Promoted {
+    Integer(i64, i64),
+    BigInteger(BigInt, BigInt),
+    Rational(BigRational, BigRational),
+    Float(f64, f64),
+    Complex(Complex64, Complex64),
+    Quantity(Quantity, Quantity),
+}
+
+/// Promotes both sides of an operation to the wider of their two types, where
+/// integers are narrower than big integers, which are narrower than rationals,
+/// which are narrower than floats, which are narrower than complex numbers.
+/// If either side has units, both are treated as quantities. Returns `None` if
+/// either side isn't a number.
+fn promote(lhs: Object, rhs: Object) -> Option<Promoted> {
+    if let (&Object::Quantity(_), _) | (_, &Object::Quantity(_)) = (&lhs, &rhs) {
+        return match (Quantity::from_object(lhs), Quantity::from_object(rhs)) {
+            (Some(lhs), Some(rhs)) => Some(Promoted::Quantity(lhs, rhs)),
+            _ => None,
+        };
+    }
+
+    if let (&Object::Integer(lhs), &Object::Integer(rhs)) = (&lhs, &rhs) {
+        return Some(Promoted::Integer(lhs, rhs));
+    }
+
+    if let (Some(lhs), Some(rhs)) = (lhs.as_big_integer(), rhs.as_big_integer()) {
+        Some(Promoted::BigInteger(lhs, rhs))
+    } else if let (Some(lhs), Some(rhs)) = (lhs.as_rational(), rhs.as_rational()) {
+        Some(Promoted::Rational(lhs, rhs))
+    } else if let (Some(lhs), Some(rhs)) = (lhs.as_float(), rhs.as_float()) {
+        Some(Promoted::Float(lhs, rhs))
+    } else if let (Some(lhs), Some(rhs)) = (lhs.as_complex(), rhs.as_complex()) {
+        Some(Promoted::Complex(lhs, rhs))
+    } else {
+        None
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Object::Integer(int) => write!(f, "{}", int),
+            Object::BigInteger(ref int) => write!(f, "{}", int),
+            Object::Rational(ref ratio) => write!(f, "{}", ratio),
+            Object::Float(float) => write!(f, "{}", float),
+            // Written the way it would be typed in, like `3-4i`.
+            Object::Complex(complex) => match (complex.re, complex.im) {
+                (0.0, im) => write!(f, "{}i", im),
+                (re, im) if im < 0.0 => write!(f, "{}-{}i", re, -im),
+                (re, im) => write!(f, "{}+{}i", re, im),
+            },
+            Object::Quantity(ref quantity) => write!(f, "{}", quantity),
+            Object::InBase(ref int, base) => {
+                let sign = if int.is_negative() { "-" } else { "" };
+                let digits = int.magnitude().to_str_radix(base.radix()).to_uppercase();
+                write!(f, "{}{}{}", sign, base.prefix(), digits)
             }
-            Object::Info(InfoType::Help) => {
-                let container: HtmlElement = document()
-                    .create_element("div")
-                    .unwrap()
-                    .try_into()
-                    .unwrap();
-                let display1 = new_text_node!("Use any of the following operations:");
-                display1.append_child::<HtmlElement>(&document()
-                    .create_element("br")
-                    .unwrap()
-                    .try_into()
-                    .unwrap());
-                display1.append_child(&document().create_text_node("+ for addition"));
-                display1.append_child::<HtmlElement>(&document()
-                    .create_element("br")
-                    .unwrap()
-                    .try_into()
-                    .unwrap());
-                display1.append_child(&document().create_text_node("- for subtraction"));
-                display1.append_child::<HtmlElement>(&document()
-                    .create_element("br")
-                    .unwrap()
-                    .try_into()
-                    .unwrap());
-                display1.append_child(&document().create_text_node("* for multiplication"));
-                display1.append_child::<HtmlElement>(&document()
-                    .create_element("br")
-                    .unwrap()
-                    .try_into()
-                    .unwrap());
-                display1.append_child(&document().create_text_node("/ for division"));
-                display1.append_child::<HtmlElement>(&document()
-                    .create_element("br")
-                    .unwrap()
-                    .try_into()
-                    .unwrap());
-                display1.append_child(&document().create_text_node("^ for exponentation"));
-                display1.append_child::<HtmlElement>(&document()
-                    .create_element("br")
-                    .unwrap()
-                    .try_into()
-                    .unwrap());
-                display1
-                    .append_child(&document().create_text_node("= for assignment of variables (ex: `a = b`)"));
-                let line_break1: HtmlElement =
-                    document().create_element("br").unwrap().try_into().unwrap();
-                let display2 = new_text_node!("Try using a few well known constants, like `pi` and `e`. `ans` is a special variable that is always the last result.");
-                let line_break2: HtmlElement =
-                    document().create_element("br").unwrap().try_into().unwrap();
-                let display3 = new_text_node!("Negative numbers are not yet supported!");
-
-                container.append_child(&display1);
-                container.append_child(&line_break1);
-                container.append_child(&display2);
-                container.append_child(&line_break2);
-                container.append_child(&display3);
-                container.class_list().add("info").unwrap();
-                Some(container)
+            Object::Boolean(boolean) => write!(f, "{}", boolean),
+            Object::List(ref items) => {
+                let items: Vec<String> = items.iter().map(ToString::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
             }
-            Object::Nil => None,
+            Object::Matrix(ref matrix) => write!(f, "{}", matrix),
+            Object::Symbolic(ref symbolic) => write!(f, "{}", symbolic),
+            Object::Plot(ref plot) => write!(f, "{}", plot),
+            Object::Error(ref error) => write!(f, "{}", error),
+            Object::Info(InfoType::About) => write!(
+                f,
+                "This is a REPL calculator made with the Rust programming language. \
+                 To see the source code, check it out on Github at \
+                 https://github.com/cgm616/calc_rs.\n\n\
+                 Try running `help()` for more info."
+            ),
+            Object::Info(InfoType::Help) => write!(
+                f,
+                "Use any of the following operations:\n\
+                 + for addition\n\
+                 - for subtraction or negation (ex: `-x`)\n\
+                 * for multiplication\n\
+                 / for division\n\
+                 ^ for exponentation\n\
+                 &, |, xor and ~ for bitwise and, or, exclusive or and not\n\
+                 << and >> for shifting bits left and right\n\
+                 ==, !=, <, <=, > and >= for comparisons (ex: `x > 0.3`)\n\
+                 and, or and not for combining comparisons\n\
+                 if, then and else for choosing (ex: `if x > 0 then x else -x`)\n\
+                 = for assignment of variables (ex: `a = b`)\n\
+                 ; for doing several things at once (ex: `a = 2; b = 3; a * b`)\n\
+                 # or /* */ for comments (ex: `g = 9.81 # at sea level`)\n\n\
+                 Call functions like `sqrt(2)` or `log(8, 2)`. The built in functions \
+                 are sqrt, abs, sin, cos, tan, asin, acos, atan, ln, log10, log, exp, \
+                 floor, ceil, round, min, max, gcd, lcm, popcount, bit, sum, product, \
+                 mean, len, transpose, det, inverse, rank and linsolve. Define your own \
+                 with `f(x, y) = x^2 + y`.\n\n\
+                 Try using a few well known constants, like `pi` and `e`. `ans` is a \
+                 special variable that is always the last result. Write imaginary \
+                 numbers with an `i`, like `3 + 4i`.\n\n\
+                 Put units after numbers, like `60 mph` or `9.8 m/s^2`, and convert \
+                 between them with `to`, like `5 km to mi`. Integers can be written \
+                 in hex, octal or binary, like `0x1F`, `0o17` or `0b1011`, and shown \
+                 that way with `to hex`, `to oct` or `to bin`.\n\n\
+                 Make lists like `v = [1, 2, 3]`, get their elements like `v[0]`, and \
+                 do arithmetic on every element at once, like `v * 2`. Lists of rows \
+                 make matrices, like `A = [[1, 2], [3, 4]]`, where `A * B` is the \
+                 matrix product and `linsolve(A, b)` solves `A x = b`.\n\n\
+                 Differentiate with `diff`, like `d = diff(x^3, x)`. The result stays \
+                 as `3 * x^2` until `x` has a value, like in `x = 2; d`. Find where \
+                 an equation holds with `solve`, like `solve(x^2 = 2, x)`, which can \
+                 be given a guess or a range to look in after `x`, or where a \
+                 function is zero between two values with `root`, like \
+                 `root(sin, 3, 4)`. Integrate with `integrate(x^2, x, 0, 1)`, and add \
+                 up or multiply a series with `sum(1/k^2, k, 1, 10)` or \
+                 `prod(k, k, 1, 5)`. Draw a chart of an expression with \
+                 `plot(sin(x), x, 0, 2pi)`, or of several at once with \
+                 `plot([sin(x), cos(x)], x, 0, 2pi)`."
+            ),
+            Object::Nil => Ok(()),
         }
     }
 }
@@ -228,7 +884,74 @@ impl From<i64> for Object {
 }
 
 impl From<f64> for Object {
+    /// Infinities and `NaN` become errors, so they can't quietly spread
+    /// through later calculations.
     fn from(num: f64) -> Object {
-        Object::Float(num)
+        if num.is_nan() {
+            Object::Error(CalcError::domain("that isn't a real number"))
+        } else if num.is_infinite() {
+            Object::Error(CalcError::overflow("that number is too large"))
+        } else {
+            Object::Float(num)
+        }
+    }
+}
+
+impl From<Complex64> for Object {
+    /// Complex numbers with no imaginary part become floats, so `i * i` is
+    /// just `-1`.
+    fn from(complex: Complex64) -> Object {
+        if complex.im == 0.0 {
+            complex.re.into()
+        } else if complex.re.is_nan() || complex.im.is_nan() {
+            Object::Error(CalcError::domain("that result is undefined"))
+        } else if complex.re.is_infinite() || complex.im.is_infinite() {
+            Object::Error(CalcError::overflow("that number is too large"))
+        } else {
+            Object::Complex(complex)
+        }
+    }
+}
+
+impl From<BigInt> for Object {
+    /// Big integers that fit in an `i64` go back to being plain integers.
+    fn from(int: BigInt) -> Object {
+        match int.to_i64() {
+            Some(int) => Object::Integer(int),
+            None => Object::BigInteger(int),
+        }
+    }
+}
+
+impl From<BigRational> for Object {
+    /// Rationals that turn out to be whole numbers become integers, so
+    /// `1/3 * 3` is just `1`.
+    fn from(ratio: BigRational) -> Object {
+        if ratio.is_integer() {
+            ratio.to_integer().into()
+        } else {
+            Object::Rational(ratio)
+        }
+    }
+}
+
+impl From<Vec<Object>> for Object {
+    /// The first error among the elements comes out on its own, rather than
+    /// as part of a list, and rows of numbers that are all the same length
+    /// make a matrix.
+    fn from(items: Vec<Object>) -> Object {
+        if let Some(position) = items.iter().position(|item| matches!(*item, Object::Error(_))) {
+            return items.into_iter().nth(position).unwrap();
+        }
+        match Matrix::from_rows(&items) {
+            Some(matrix) => Object::Matrix(matrix),
+            None => Object::List(items),
+        }
+    }
+}
+
+impl From<CalcError> for Object {
+    fn from(error: CalcError) -> Object {
+        Object::Error(error)
     }
 }
@@ -0,0 +1,253 @@
+use std::fmt;
+
+/// How many steps `plot` divides its range into, so it evaluates the
+/// expression one more time than this.
+const STEPS: usize = 200;
+
+/// The size of a plot, in pixels.
+const WIDTH: f64 = 480.0;
+const HEIGHT: f64 = 300.0;
+
+/// Space around the chart for the tick labels, in pixels.
+const LEFT: f64 = 56.0;
+const RIGHT: f64 = 12.0;
+const TOP: f64 = 12.0;
+const BOTTOM: f64 = 28.0;
+
+/// About how many ticks go along each axis, and the most there can be.
+const TICKS: f64 = 6.0;
+const MAX_TICKS: i64 = 20;
+
+/// The most decimal places or digits before the point a tick label can have
+/// before it is written like `2.5e-9` instead, so that it fits in the margin.
+const MAX_DECIMALS: usize = 4;
+const MAX_DIGITS: usize = 6;
+
+/// The colors of the lines, used in turn. They show up on both the black
+/// console and a white page.
+const COLORS: &[&str] = &["#4fc3f7", "#ff8a65", "#aed581", "#ffd54f", "#ba68c8", "#f06292"];
+
+/// An expression sampled over a range of its variable, like
+/// `plot(sin(x), x, 0, 2pi)`, which is shown as a line chart.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Plot {
+    pub variable: String,
+    pub from: f64,
+    pub to: f64,
+    pub series: Vec<Series>,
+}
+
+/// One line of a plot. There is a value for each of the evenly spaced points
+/// from the start of the range to the end, or `None` where the expression
+/// wasn't a real number, which leaves a gap.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Series {
+    pub label: String,
+    pub values: Vec<Option<f64>>,
+}
+
+impl Plot {
+    /// The value of the variable at each point that was sampled.
+    pub fn points(&self) -> Vec<f64> {
+        let step = (self.to - self.from) / STEPS as f64;
+        (0..=STEPS).map(|index| self.from + step * index as f64).collect()
+    }
+
+    /// The lowest and highest values of every line, spread apart a little if
+    /// they are the same so that a flat line sits in the middle.
+    fn range(&self) -> (f64, f64) {
+        let values = self.series.iter().flat_map(|series| series.values.iter().flatten());
+        let (low, high) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), &y| {
+            (low.min(y), high.max(y))
+        });
+        if low < high {
+            (low, high)
+        } else {
+            let spread = if low == 0.0 { 1.0 } else { low.abs() / 2.0 };
+            (low - spread, high + spread)
+        }
+    }
+}
+
+/// Round numbers between `low` and `high` to put ticks at, going up in steps
+/// of 1, 2 or 5 times a power of ten, each with its label.
+fn ticks(low: f64, high: f64) -> Vec<(f64, String)> {
+    let rough = (high - low) / TICKS;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|factor| factor * magnitude)
+        .find(|&step| step >= rough)
+        .unwrap();
+    // A range too narrow to tell its ends apart, like from 0 to `5e-324`, has
+    // no round numbers in it to put ticks at.
+    if !step.is_finite() || step <= 0.0 {
+        return Vec::new();
+    }
+
+    let first = (low / step).ceil() as i64;
+    let last = (high / step).floor() as i64;
+    if last - first >= MAX_TICKS {
+        return Vec::new();
+    }
+    let ticks: Vec<f64> = (first..=last).map(|index| index as f64 * step).collect();
+
+    // Every label on an axis is written the same way, in exponent notation
+    // if there would be too many digits otherwise and that is shorter.
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    let mut labels: Vec<String> =
+        ticks.iter().map(|tick| format!("{:.*}", decimals, tick)).collect();
+    let largest = ticks.iter().fold(0f64, |largest, tick| largest.max(tick.abs()));
+    let digits = largest.log10().floor().max(0.0) as usize + 1;
+    if decimals > MAX_DECIMALS || digits > MAX_DIGITS {
+        let exponents: Vec<String> = ticks
+            .iter()
+            .map(|&tick| if tick == 0.0 { "0".to_string() } else { exponent_label(tick, step) })
+            .collect();
+        let longest = |labels: &[String]| labels.iter().map(String::len).max();
+        if longest(&exponents) < longest(&labels) {
+            labels = exponents;
+        }
+    }
+    ticks.into_iter().zip(labels).collect()
+}
+
+/// A tick in exponent notation, with as many digits as it takes to tell it
+/// apart from the ticks `step` either side of it, like `1.5e-9`.
+fn exponent_label(tick: f64, step: f64) -> String {
+    let precision = (tick.abs().log10().floor() - step.log10().floor()).max(0.0) as usize;
+    let label = format!("{:.*e}", precision, tick);
+    // `1.0e-9` is just `1e-9`.
+    let (mantissa, exponent) = label.split_at(label.find('e').unwrap());
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        mantissa
+    };
+    format!("{}{}", mantissa, exponent)
+}
+
+/// Escapes text to go inside an SVG element.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Shown as an SVG document, which the web console puts inline and which can
+/// be saved from the command line, like `calc plot.calc > plot.svg`.
+impl fmt::Display for Plot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (low, high) = self.range();
+        let (left, right) = (LEFT, WIDTH - RIGHT);
+        let (top, bottom) = (TOP, HEIGHT - BOTTOM);
+        let x_of = |x: f64| left + (x - self.from) / (self.to - self.from) * (right - left);
+        let y_of = |y: f64| bottom - (y - low) / (high - low) * (bottom - top);
+
+        writeln!(
+            f,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+             viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\" font-size=\"11\">",
+            w = WIDTH,
+            h = HEIGHT
+        )?;
+
+        // The grid and ticks, with the lines for zero drawn more strongly.
+        let (x_low, x_high) = (self.from.min(self.to), self.from.max(self.to));
+        for (x, label) in ticks(x_low, x_high) {
+            let opacity = if x == 0.0 { 0.6 } else { 0.15 };
+            writeln!(
+                f,
+                "<line x1=\"{x:.1}\" y1=\"{}\" x2=\"{x:.1}\" y2=\"{}\" stroke=\"currentColor\" \
+                 stroke-opacity=\"{}\"/>",
+                top,
+                bottom,
+                opacity,
+                x = x_of(x)
+            )?;
+            writeln!(
+                f,
+                "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\" fill=\"currentColor\">\
+                 {}</text>",
+                x_of(x),
+                bottom + 16.0,
+                label
+            )?;
+        }
+        for (y, label) in ticks(low, high) {
+            let opacity = if y == 0.0 { 0.6 } else { 0.15 };
+            writeln!(
+                f,
+                "<line x1=\"{}\" y1=\"{y:.1}\" x2=\"{}\" y2=\"{y:.1}\" stroke=\"currentColor\" \
+                 stroke-opacity=\"{}\"/>",
+                left,
+                right,
+                opacity,
+                y = y_of(y)
+            )?;
+            writeln!(
+                f,
+                "<text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\" dominant-baseline=\"middle\" \
+                 fill=\"currentColor\">{}</text>",
+                left - 6.0,
+                y_of(y),
+                label
+            )?;
+        }
+
+        // The axes along the bottom and left, and the variable's name.
+        writeln!(
+            f,
+            "<polyline points=\"{l},{t} {l},{b} {r},{b}\" fill=\"none\" stroke=\"currentColor\"/>",
+            l = left,
+            t = top,
+            b = bottom,
+            r = right
+        )?;
+        writeln!(
+            f,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"end\" fill=\"currentColor\">{}</text>",
+            right,
+            HEIGHT - 2.0,
+            escape(&self.variable)
+        )?;
+
+        // Each line is broken wherever there is a gap in its values.
+        let points = self.points();
+        for (series, color) in self.series.iter().zip(COLORS.iter().cycle()) {
+            let mut segments = vec![Vec::new()];
+            for (&x, value) in points.iter().zip(&series.values) {
+                match *value {
+                    Some(y) => segments
+                        .last_mut()
+                        .unwrap()
+                        .push(format!("{:.1},{:.1}", x_of(x), y_of(y))),
+                    None => segments.push(Vec::new()),
+                }
+            }
+            for segment in segments.iter().filter(|segment| !segment.is_empty()) {
+                writeln!(
+                    f,
+                    "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"/>",
+                    segment.join(" "),
+                    color
+                )?;
+            }
+        }
+
+        // Which line is which, if there is more than one.
+        if self.series.len() > 1 {
+            let colors = COLORS.iter().cycle();
+            for (index, (series, color)) in self.series.iter().zip(colors).enumerate() {
+                writeln!(
+                    f,
+                    "<text x=\"{}\" y=\"{}\" text-anchor=\"end\" fill=\"{}\">{}</text>",
+                    right - 4.0,
+                    top + 12.0 + 14.0 * index as f64,
+                    color,
+                    escape(&series.label)
+                )?;
+            }
+        }
+
+        write!(f, "</svg>")
+    }
+}
@@ -0,0 +1,338 @@
+use num_traits::ToPrimitive;
+use std::{cmp::Ordering,
+          fmt,
+          ops::{Add, Div, Mul, Neg, Rem, Sub}};
+
+use super::{CalcError, Object};
+
+/// The names of the base units that dimensions are made of, in the order
+/// their exponents are stored in a `Dimension`.
+const BASE_UNITS: [&str; 8] = ["kg", "m", "s", "A", "K", "mol", "cd", "bit"];
+
+/// What a quantity measures, as the power of each base unit it is made of,
+/// so that a speed is `m^1 s^-1`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Dimension([i32; 8]);
+
+impl Dimension {
+    pub const MASS: Dimension = Dimension([1, 0, 0, 0, 0, 0, 0, 0]);
+    pub const LENGTH: Dimension = Dimension([0, 1, 0, 0, 0, 0, 0, 0]);
+    pub const TIME: Dimension = Dimension([0, 0, 1, 0, 0, 0, 0, 0]);
+    pub const CURRENT: Dimension = Dimension([0, 0, 0, 1, 0, 0, 0, 0]);
+    pub const TEMPERATURE: Dimension = Dimension([0, 0, 0, 0, 1, 0, 0, 0]);
+    pub const AMOUNT: Dimension = Dimension([0, 0, 0, 0, 0, 1, 0, 0]);
+    pub const LUMINOSITY: Dimension = Dimension([0, 0, 0, 0, 0, 0, 1, 0]);
+    pub const INFORMATION: Dimension = Dimension([0, 0, 0, 0, 0, 0, 0, 1]);
+
+    /// Whether this is the dimension of a plain number.
+    pub fn is_none(&self) -> bool {
+        self.0.iter().all(|&exponent| exponent == 0)
+    }
+
+    /// Raises the dimension to `numer/denom`, or returns `None` if that would
+    /// leave a fractional power of some base unit, like `m^(1/2)`.
+    pub fn pow(self, numer: i32, denom: i32) -> Option<Dimension> {
+        let mut result = self;
+        for exponent in result.0.iter_mut() {
+            let scaled = exponent.checked_mul(numer)?;
+            if scaled % denom != 0 {
+                return None;
+            }
+            *exponent = scaled / denom;
+        }
+        Some(result)
+    }
+
+    fn combine<F: Fn(i32, i32) -> i32>(self, rhs: Dimension, function: F) -> Dimension {
+        let mut result = self;
+        for (exponent, rhs) in result.0.iter_mut().zip(rhs.0.iter()) {
+            *exponent = function(*exponent, *rhs);
+        }
+        result
+    }
+}
+
+impl Mul for Dimension {
+    type Output = Dimension;
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.combine(rhs, |lhs, rhs| lhs + rhs)
+    }
+}
+
+impl Div for Dimension {
+    type Output = Dimension;
+    fn div(self, rhs: Self) -> Self::Output {
+        self.combine(rhs, |lhs, rhs| lhs - rhs)
+    }
+}
+
+impl fmt::Display for Dimension {
+    /// Writes the dimension in base units, like `kg*m/s^2`, or with negative
+    /// powers if there is nothing to divide, like `m^-1`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn powers<F: Fn(i32) -> bool>(dimension: &Dimension, wanted: F, sign: i32) -> Vec<String> {
+            BASE_UNITS
+                .iter()
+                .zip(dimension.0.iter())
+                .filter(|&(_, &exponent)| wanted(exponent))
+                .map(|(name, &exponent)| match exponent * sign {
+                    1 => name.to_string(),
+                    power => format!("{}^{}", name, power),
+                })
+                .collect()
+        }
+
+        let above = powers(self, |exponent| exponent > 0, 1);
+        if above.is_empty() {
+            return write!(f, "{}", powers(self, |exponent| exponent < 0, 1).join("*"));
+        }
+
+        write!(f, "{}", above.join("*"))?;
+        let below = powers(self, |exponent| exponent < 0, -1);
+        match below.len() {
+            0 => Ok(()),
+            1 => write!(f, "/{}", below[0]),
+            _ => write!(f, "/({})", below.join("*")),
+        }
+    }
+}
+
+/// A named unit that a quantity is shown in, like `km`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Unit {
+    pub name: String,
+    /// How many base units one of this unit is.
+    pub scale: Box<Object>,
+}
+
+/// A number with a unit, like `3 m` or `9.8 m/s^2`.
+///
+/// The value is always kept in base units, so `5 km` is stored as `5000` of
+/// `m`, and the unit it was written in is only remembered for showing it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Quantity {
+    pub value: Box<Object>,
+    pub dimension: Dimension,
+    pub unit: Option<Unit>,
+}
+
+impl Quantity {
+    /// One of a unit called `name`, which is `scale` base units of
+    /// `dimension`.
+    pub fn unit(name: &str, scale: Object, dimension: Dimension) -> Quantity {
+        Quantity {
+            value: Box::new(scale.clone()),
+            dimension,
+            unit: Some(Unit {
+                name: name.to_string(),
+                scale: Box::new(scale),
+            }),
+        }
+    }
+
+    /// Treats a plain number as a quantity without dimensions. Returns `None`
+    /// if the object isn't a number at all.
+    pub fn from_object(object: Object) -> Option<Quantity> {
+        match object {
+            Object::Quantity(quantity) => Some(quantity),
+            number => number.as_complex().map(|_| Quantity {
+                value: Box::new(number),
+                dimension: Dimension::default(),
+                unit: None,
+            }),
+        }
+    }
+
+    /// Shows the quantity in `unit` from now on, as long as it measures the
+    /// same thing.
+    pub fn convert(self, unit: Quantity, name: &str) -> Object {
+        if self.dimension != unit.dimension {
+            return Object::Error(CalcError::units(format!(
+                "can't convert {} to {}",
+                describe(self.dimension),
+                name
+            )));
+        }
+
+        Object::Quantity(Quantity {
+            value: self.value,
+            dimension: self.dimension,
+            unit: Some(Unit {
+                name: name.to_string(),
+                scale: unit.value,
+            }),
+        })
+    }
+
+    /// Raises the quantity to a power, which has to be a plain number that
+    /// leaves whole powers of each unit, so `(4 m^2)^(1/2)` works but
+    /// `(4 m)^(1/2)` doesn't.
+    pub fn pow(self, exponent: Object) -> Object {
+        let (numer, denom) = match exponent {
+            Object::Integer(int) => (int.to_i32(), Some(1)),
+            Object::Rational(ref ratio) => (ratio.numer().to_i32(), ratio.denom().to_i32()),
+            Object::Float(float) if float.fract() == 0.0 => (float.to_i32(), Some(1)),
+            Object::Quantity(_) => {
+                return Object::Error(CalcError::units("exponents can't have units"))
+            }
+            _ => (None, None),
+        };
+
+        let dimension = match (numer, denom) {
+            (Some(numer), Some(denom)) => self.dimension.pow(numer, denom),
+            _ => None,
+        };
+        match dimension {
+            Some(dimension) => Quantity {
+                value: Box::new(self.value.pow(exponent)),
+                dimension,
+                unit: None,
+            }.into(),
+            None => Object::Error(CalcError::units(format!(
+                "{} can't be raised to that power",
+                describe(self.dimension)
+            ))),
+        }
+    }
+
+    /// Compares two quantities that measure the same thing.
+    pub fn compare(&self, other: &Quantity) -> Option<Ordering> {
+        if self.dimension != other.dimension {
+            return None;
+        }
+        self.value.compare(&other.value)
+    }
+
+    /// The values of two quantities, as long as they measure the same thing
+    /// so that it makes sense to `verb` them.
+    pub fn values(self, verb: &str, rhs: Quantity) -> Result<(Object, Object), CalcError> {
+        if self.dimension != rhs.dimension {
+            return Err(CalcError::units(format!(
+                "can't {} {} and {}",
+                verb,
+                describe(self.dimension),
+                describe(rhs.dimension)
+            )));
+        }
+        Ok((*self.value, *rhs.value))
+    }
+
+    fn same_dimension<F>(mut self, verb: &str, rhs: Quantity, function: F) -> Object
+    where
+        F: FnOnce(Object, Object) -> Object,
+    {
+        let dimension = self.dimension;
+        let unit = self.unit.take();
+        match self.values(verb, rhs) {
+            Ok((lhs, rhs)) => Quantity {
+                value: Box::new(function(lhs, rhs)),
+                dimension,
+                unit,
+            }.into(),
+            Err(error) => Object::Error(error),
+        }
+    }
+}
+
+impl Add for Quantity {
+    type Output = Object;
+    fn add(self, rhs: Self) -> Self::Output {
+        self.same_dimension("add", rhs, |lhs, rhs| lhs + rhs)
+    }
+}
+
+impl Sub for Quantity {
+    type Output = Object;
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.same_dimension("subtract", rhs, |lhs, rhs| lhs - rhs)
+    }
+}
+
+impl Mul for Quantity {
+    type Output = Object;
+    fn mul(self, rhs: Self) -> Self::Output {
+        // Scaling a quantity by a plain number keeps its unit, so `2 * 3 km`
+        // is `6 km`.
+        let unit = match (self.unit, rhs.unit) {
+            (unit, _) if rhs.dimension.is_none() => unit,
+            (_, unit) if self.dimension.is_none() => unit,
+            _ => None,
+        };
+
+        Quantity {
+            value: Box::new(*self.value * *rhs.value),
+            dimension: self.dimension * rhs.dimension,
+            unit,
+        }.into()
+    }
+}
+
+impl Div for Quantity {
+    type Output = Object;
+    fn div(self, rhs: Self) -> Self::Output {
+        let unit = if rhs.dimension.is_none() {
+            self.unit
+        } else {
+            None
+        };
+
+        Quantity {
+            value: Box::new(*self.value / *rhs.value),
+            dimension: self.dimension / rhs.dimension,
+            unit,
+        }.into()
+    }
+}
+
+impl Rem for Quantity {
+    type Output = Object;
+    fn rem(self, rhs: Self) -> Self::Output {
+        self.same_dimension("take the remainder of", rhs, |lhs, rhs| lhs % rhs)
+    }
+}
+
+impl Neg for Quantity {
+    type Output = Object;
+    fn neg(self) -> Self::Output {
+        Quantity {
+            value: Box::new(-*self.value),
+            ..self
+        }.into()
+    }
+}
+
+/// How a dimension is named in errors.
+fn describe(dimension: Dimension) -> String {
+    if dimension.is_none() {
+        "a number without units".to_string()
+    } else {
+        dimension.to_string()
+    }
+}
+
+impl From<Quantity> for Object {
+    /// Errors in the value come out on their own, and quantities whose units
+    /// cancel out, like `5 km / m`, become plain numbers.
+    fn from(quantity: Quantity) -> Object {
+        match *quantity.value {
+            Object::Error(error) => Object::Error(error),
+            value if quantity.dimension.is_none() => value,
+            _ => Object::Quantity(quantity),
+        }
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (value, unit) = match self.unit {
+            Some(ref unit) => (*self.value.clone() / *unit.scale.clone(), unit.name.clone()),
+            None => (*self.value.clone(), self.dimension.to_string()),
+        };
+
+        // Conversions rarely come out as tidy fractions, so show decimals.
+        match value {
+            Object::Rational(ref ratio) => write!(f, "{} {}", ratio.to_f64().unwrap(), unit),
+            value => write!(f, "{} {}", value, unit),
+        }
+    }
+}
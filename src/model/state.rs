@@ -1,16 +1,25 @@
-use std::{self, cell::RefCell, collections::HashMap, rc::Rc};
+use std::{self, collections::HashMap};
 
-use super::Object;
+use super::{Function, Object, Quantity};
+use units;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct State {
     pub history: Vec<String>,
     pub assignments: HashMap<String, Object>,
+    #[serde(default)]
+    pub functions: HashMap<String, Function>,
+    // Units come from this build rather than the save, so that new ones show
+    // up for everyone.
+    #[serde(skip, default = "units::table")]
+    pub units: HashMap<String, Quantity>,
     counter: Option<usize>,
+    // How many user defined functions are currently being called, to stop
+    // runaway recursion.
+    #[serde(skip)]
+    pub(crate) depth: usize,
 }
 
-pub type StateRef = Rc<RefCell<State>>;
-
 impl State {
     pub fn new() -> Self {
         let mut map = HashMap::new();
@@ -21,7 +30,10 @@ impl State {
         State {
             history: Vec::new(),
             assignments: map,
+            functions: HashMap::new(),
+            units: units::table(),
             counter: None,
+            depth: 0,
         }
     }
 
@@ -33,7 +45,7 @@ impl State {
     }
 
     pub fn next_history(&mut self) -> Option<&str> {
-        if self.history.len() > 0 {
+        if !self.history.is_empty() {
             match self.counter {
                 Some(num) => {
                     if num > 0 {
@@ -55,7 +67,7 @@ impl State {
     }
 
     pub fn previous_history(&mut self) -> Option<&str> {
-        if self.history.len() > 0 {
+        if !self.history.is_empty() {
             match self.counter {
                 Some(num) => {
                     if num < self.history.len() - 1 {
@@ -77,15 +89,54 @@ impl State {
         self.counter = None;
     }
 
+    /// Runs `body` with `bindings` layered over the assignments, so that they
+    /// shadow any variables with the same names until it returns.
+    pub fn with_bindings<T, F>(&mut self, bindings: Vec<(String, Object)>, body: F) -> T
+    where
+        F: FnOnce(&mut State) -> T,
+    {
+        let shadowed: Vec<(String, Option<Object>)> = bindings
+            .into_iter()
+            .map(|(name, value)| {
+                let old = self.assignments.insert(name.clone(), value);
+                (name, old)
+            })
+            .collect();
+
+        let result = body(self);
+
+        // Restore in reverse so that a name bound twice ends up as it began.
+        for (name, old) in shadowed.into_iter().rev() {
+            match old {
+                Some(old) => self.assignments.insert(name, old),
+                None => self.assignments.remove(&name),
+            };
+        }
+
+        result
+    }
+
     pub fn set_ans(&mut self, object: &Object) {
-        match object {
-            &Object::Integer(int) => {
-                self.assignments.insert("ans".to_string(), int.into());
-            }
-            &Object::Float(float) => {
-                self.assignments.insert("ans".to_string(), float.into());
+        match *object {
+            Object::Integer(_)
+            | Object::BigInteger(_)
+            | Object::Rational(_)
+            | Object::Float(_)
+            | Object::Complex(_)
+            | Object::Quantity(_)
+            | Object::InBase(..)
+            | Object::List(_)
+            | Object::Matrix(_)
+            | Object::Symbolic(_) => {
+                self.assignments.insert("ans".to_string(), object.clone());
             }
             _ => {}
         };
     }
 }
+
+impl Default for State {
+    fn default() -> Self {
+        State::new()
+    }
+}
@@ -0,0 +1,385 @@
+//! Numerical methods that work on a function the engine evaluates, like
+//! finding where an equation holds or the area under a curve.
+//!
+//! The function is passed in as a closure. For the root finders it gives
+//! `Ok(None)` at points where it isn't a real number, like `ln(x)` for
+//! negative `x`, and an error for anything that means it will never work,
+//! like an unknown variable.
+
+use model::CalcError;
+
+/// How many times the distance from the starting point doubles while looking
+/// for a sign change, which reaches about 10^18 times the first step.
+const MAX_EXPANSIONS: usize = 64;
+
+/// How many steps the root finders take before giving up.
+const MAX_ITERATIONS: usize = 200;
+
+/// A point that has been evaluated, along with the function's value there.
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Finds a point near `guess` where `f` is zero.
+///
+/// Steps of doubling size are taken out in both directions until `f` changes
+/// sign, and the root in between is found with Brent's method. If `f` only
+/// touches zero without crossing it, like `x^2`, the secant method is tried
+/// from `guess` instead. Returns `Ok(None)` if neither finds a root.
+pub fn find_root<F>(f: &mut F, guess: f64) -> Result<Option<f64>, CalcError>
+where
+    F: FnMut(f64) -> Result<Option<f64>, CalcError>,
+{
+    let start = f(guess)?.map(|y| Sample { x: guess, y });
+    if let Some(Sample { y, .. }) = start {
+        if y == 0.0 {
+            return Ok(Some(guess));
+        }
+    }
+
+    let step = 0.1 * guess.abs().max(1.0);
+    let mut sides = [start, start];
+    for expansion in 0..MAX_EXPANSIONS {
+        let distance = step * 2f64.powi(expansion as i32);
+        for (side, direction) in sides.iter_mut().zip(&[1.0, -1.0]) {
+            let x = guess + direction * distance;
+            let sample = f(x)?.map(|y| Sample { x, y });
+            let bracket = match (*side, sample) {
+                (Some(previous), Some(next)) => Some((previous, next)),
+                // A gap where `f` isn't defined can't be bracketed across,
+                // but there may be a root before it.
+                (Some(previous), None) => edge(f, previous, x)?,
+                _ => None,
+            };
+            if let Some((previous, next)) = bracket {
+                if next.y == 0.0 {
+                    return Ok(Some(next.x));
+                }
+                if previous.y.signum() != next.y.signum() {
+                    if let Some(root) = brent(f, previous, next)? {
+                        return Ok(Some(root));
+                    }
+                }
+            }
+            *side = sample;
+        }
+    }
+
+    secant(f, guess, step)
+}
+
+/// Looks between `inside`, where `f` is defined, and `outside`, where it
+/// isn't, for a point where `f` has the opposite sign, like where `exp(x)`
+/// passes `1e300` before getting too large to work out. Returns the last
+/// point with the same sign along with it, or `None` if there isn't one.
+fn edge<F>(f: &mut F, inside: Sample, outside: f64) -> Result<Option<(Sample, Sample)>, CalcError>
+where
+    F: FnMut(f64) -> Result<Option<f64>, CalcError>,
+{
+    let (mut inside, mut outside) = (inside, outside);
+    for _ in 0..MAX_ITERATIONS {
+        let x = 0.5 * (inside.x + outside);
+        if x == inside.x || x == outside {
+            break;
+        }
+        match f(x)? {
+            Some(y) if y == 0.0 || y.signum() != inside.y.signum() => {
+                return Ok(Some((inside, Sample { x, y })))
+            }
+            Some(y) => inside = Sample { x, y },
+            None => outside = x,
+        }
+    }
+    Ok(None)
+}
+
+/// Finds a root of `f` between two points where it has opposite signs, with
+/// Brent's method: inverse quadratic interpolation and secant steps where
+/// they make progress, and bisection where they don't.
+///
+/// Returns `Ok(None)` if `f` isn't defined somewhere it looks, or if the sign
+/// change turns out to be a jump rather than a root, like `1/x` at zero.
+pub fn brent<F>(f: &mut F, a: Sample, b: Sample) -> Result<Option<f64>, CalcError>
+where
+    F: FnMut(f64) -> Result<Option<f64>, CalcError>,
+{
+    let bound = a.y.abs().max(b.y.abs());
+    let (mut a, mut b) = (a, b);
+    let mut c = a;
+    let mut d = b.x - a.x;
+    let mut e = d;
+
+    for _ in 0..MAX_ITERATIONS {
+        if b.y.signum() == c.y.signum() {
+            c = a;
+            d = b.x - a.x;
+            e = d;
+        }
+        // Keep `b` as the best estimate so far.
+        if c.y.abs() < b.y.abs() {
+            a = b;
+            b = c;
+            c = a;
+        }
+
+        let tolerance = 2.0 * f64::EPSILON * b.x.abs() + 1e-300;
+        let midpoint = 0.5 * (c.x - b.x);
+        if midpoint.abs() <= tolerance || b.y == 0.0 {
+            return Ok(if b.y.abs() <= bound { Some(b.x) } else { None });
+        }
+
+        if e.abs() >= tolerance && a.y.abs() > b.y.abs() {
+            let s = b.y / a.y;
+            let (mut p, mut q) = if a.x == c.x {
+                (2.0 * midpoint * s, 1.0 - s)
+            } else {
+                let q = a.y / c.y;
+                let r = b.y / c.y;
+                (
+                    s * (2.0 * midpoint * q * (q - r) - (b.x - a.x) * (r - 1.0)),
+                    (q - 1.0) * (r - 1.0) * (s - 1.0),
+                )
+            };
+            if p > 0.0 {
+                q = -q;
+            }
+            p = p.abs();
+            let limit = (3.0 * midpoint * q - (tolerance * q).abs()).min((e * q).abs());
+            if 2.0 * p < limit {
+                e = d;
+                d = p / q;
+            } else {
+                d = midpoint;
+                e = d;
+            }
+        } else {
+            d = midpoint;
+            e = d;
+        }
+
+        a = b;
+        let x = if d.abs() > tolerance {
+            b.x + d
+        } else {
+            b.x + tolerance.copysign(midpoint)
+        };
+        b = match f(x)? {
+            Some(y) => Sample { x, y },
+            None => return Ok(None),
+        };
+    }
+
+    Ok(None)
+}
+
+/// Finds a root of `f` with the secant method, starting from `x` and a point
+/// `step` away from it. Unlike Brent's method it doesn't need a sign change,
+/// but it can wander off, so it only counts if it settles down.
+fn secant<F>(f: &mut F, x: f64, step: f64) -> Result<Option<f64>, CalcError>
+where
+    F: FnMut(f64) -> Result<Option<f64>, CalcError>,
+{
+    let mut previous = match f(x)? {
+        Some(y) => Sample { x, y },
+        None => return Ok(None),
+    };
+    let mut current = match f(x + step)? {
+        Some(y) => Sample { x: x + step, y },
+        None => return Ok(None),
+    };
+    let scale = previous.y.abs().max(current.y.abs());
+
+    for _ in 0..MAX_ITERATIONS {
+        if current.y == 0.0 {
+            return Ok(Some(current.x));
+        }
+        let slope = (current.y - previous.y) / (current.x - previous.x);
+        if slope == 0.0 || !slope.is_finite() {
+            break;
+        }
+        let x = current.x - current.y / slope;
+        let y = match f(x)? {
+            Some(y) => y,
+            None => return Ok(None),
+        };
+        previous = current;
+        current = Sample { x, y };
+        if (current.x - previous.x).abs() <= 4.0 * f64::EPSILON * current.x.abs() {
+            break;
+        }
+    }
+
+    let settled = (current.x - previous.x).abs() <= 1e-9 * current.x.abs().max(1.0)
+        && current.y.abs() <= 1e-12 * scale.max(1.0);
+    Ok(if settled { Some(current.x) } else { None })
+}
+
+/// `x` rounded to 12 significant digits, or to 15 decimal places if that is
+/// fewer, if `f` is at least as close to zero there. That way an exact root
+/// like `0.05` isn't shown as `0.05000000000000001`, and one at zero that was
+/// only approached isn't shown as `1e-42`.
+pub fn tidy<F>(f: &mut F, x: f64) -> Result<f64, CalcError>
+where
+    F: FnMut(f64) -> Result<Option<f64>, CalcError>,
+{
+    if x == 0.0 || !x.is_finite() {
+        return Ok(x);
+    }
+    let digits = (11 - x.abs().log10().floor() as i32).min(15);
+    let scale = 10f64.powi(digits);
+    let rounded = (x * scale).round() / scale;
+    if !rounded.is_finite() || rounded == x {
+        return Ok(x);
+    }
+
+    Ok(match (f(rounded)?, f(x)?) {
+        (Some(near), Some(at)) if near.abs() <= at.abs() => rounded,
+        _ => x,
+    })
+}
+
+/// How many pieces `integrate` splits its range into before giving up.
+const MAX_PIECES: usize = 2000;
+
+/// How close `integrate` gets to the area, relative to its size.
+const RELATIVE_TOLERANCE: f64 = 1e-13;
+
+/// The nodes of the 15 point Kronrod rule on `[-1, 1]`, from the outside in.
+/// Only the positive half is listed, since the nodes are symmetric, and every
+/// other one is also a node of the 7 point Gauss rule.
+const KRONROD_NODES: [f64; 8] = [
+    0.9914553711208126,
+    0.9491079123427585,
+    0.8648644233597691,
+    0.7415311855993945,
+    0.5860872354676911,
+    0.4058451513773972,
+    0.20778495500789848,
+    0.0,
+];
+
+const KRONROD_WEIGHTS: [f64; 8] = [
+    0.022935322010529224,
+    0.06309209262997856,
+    0.10479001032225019,
+    0.14065325971552592,
+    0.1690047266392679,
+    0.19035057806478542,
+    0.20443294007529889,
+    0.20948214108472782,
+];
+
+/// The weights of the 7 point Gauss rule, for every other Kronrod node.
+const GAUSS_WEIGHTS: [f64; 4] = [
+    0.1294849661688697,
+    0.27970539148927664,
+    0.3818300505051189,
+    0.4179591836734694,
+];
+
+/// Part of the range being integrated, with its estimated area.
+#[derive(Clone, Copy, Debug)]
+struct Piece {
+    a: f64,
+    b: f64,
+    area: f64,
+    error: f64,
+    // The area under `|f|`, which says how much cancellation went on.
+    magnitude: f64,
+}
+
+/// The area under `f` between `a` and `b`, by adaptive Gauss-Kronrod
+/// quadrature.
+///
+/// The piece of the range with the biggest error is cut in half again and
+/// again until the whole area is accurate enough. `f` is never evaluated at
+/// the ends of a piece, so something like `1/sqrt(x)` from 0 works. Returns
+/// `Ok(None)` if the area doesn't settle down, like for `1/x` from 0.
+pub fn integrate<F>(f: &mut F, a: f64, b: f64) -> Result<Option<f64>, CalcError>
+where
+    F: FnMut(f64) -> Result<f64, CalcError>,
+{
+    let mut pieces = vec![kronrod(f, a, b)?];
+    loop {
+        let (mut area, mut error, mut magnitude) = (0.0, 0.0, 0.0);
+        for piece in &pieces {
+            area += piece.area;
+            error += piece.error;
+            magnitude += piece.magnitude;
+        }
+        if !area.is_finite() || !error.is_finite() {
+            return Err(CalcError::overflow("that number is too large"));
+        }
+        if error <= (RELATIVE_TOLERANCE * area.abs()).max(1e-15 * magnitude) {
+            // What is left of an area that cancels out is rounding error.
+            return Ok(Some(if area.abs() <= 1e-14 * magnitude { 0.0 } else { area }));
+        }
+        if pieces.len() >= MAX_PIECES {
+            return Ok(None);
+        }
+
+        let worst = (0..pieces.len())
+            .max_by(|&i, &j| pieces[i].error.total_cmp(&pieces[j].error))
+            .unwrap();
+        let Piece { a, b, .. } = pieces[worst];
+        let middle = 0.5 * (a + b);
+        if middle == a || middle == b {
+            return Ok(None);
+        }
+        pieces[worst] = kronrod(f, a, middle)?;
+        pieces.push(kronrod(f, middle, b)?);
+    }
+}
+
+/// Estimates the area under `f` from `a` to `b` with the 15 point Kronrod
+/// rule, and how far off it is from how much the 7 point Gauss rule using
+/// the same points disagrees.
+fn kronrod<F>(f: &mut F, a: f64, b: f64) -> Result<Piece, CalcError>
+where
+    F: FnMut(f64) -> Result<f64, CalcError>,
+{
+    let center = 0.5 * (a + b);
+    let half = 0.5 * (b - a);
+
+    let middle = f(center)?;
+    let mut kronrod = KRONROD_WEIGHTS[7] * middle;
+    let mut gauss = GAUSS_WEIGHTS[3] * middle;
+    let mut magnitude = KRONROD_WEIGHTS[7] * middle.abs();
+    for (index, node) in KRONROD_NODES[..7].iter().enumerate() {
+        let (left, right) = (f(center - half * node)?, f(center + half * node)?);
+        kronrod += KRONROD_WEIGHTS[index] * (left + right);
+        magnitude += KRONROD_WEIGHTS[index] * (left.abs() + right.abs());
+        if index % 2 == 1 {
+            gauss += GAUSS_WEIGHTS[index / 2] * (left + right);
+        }
+    }
+
+    let (area, error) = (kronrod * half, ((kronrod - gauss) * half).abs());
+    if !area.is_finite() || !error.is_finite() || !magnitude.is_finite() {
+        return Err(CalcError::overflow("that number is too large"));
+    }
+    Ok(Piece {
+        a,
+        b,
+        area,
+        error,
+        magnitude: magnitude * half.abs(),
+    })
+}
+
+/// `x` rounded to 12 significant digits if that only undoes rounding error,
+/// so that an area of `9` isn't shown as `9.000000000000002`.
+pub fn snap(x: f64) -> f64 {
+    if x == 0.0 || !x.is_finite() {
+        return x;
+    }
+    let scale = 10f64.powi(11 - x.abs().log10().floor() as i32);
+    let rounded = (x * scale).round() / scale;
+    if rounded.is_finite() && (rounded - x).abs() <= 1e-14 * x.abs() {
+        rounded
+    } else {
+        x
+    }
+}
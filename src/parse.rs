@@ -1,5 +1,5 @@
 #[cfg(debug_assertions)]
-const _GRAMMAR: &'static str = include_str!("calc.pest");
+const _GRAMMAR: &str = include_str!("calc.pest");
 
 #[derive(Parser)]
 #[grammar = "calc.pest"]
@@ -26,9 +26,13 @@ mod test {
         parses_to! {
             parser: CalcParser,
             input: "-143",
-            rule: Rule::int,
+            rule: Rule::expr,
             tokens: [
-                int(0, 4)
+                expr(0, 4, [
+                    neg(0, 4, [
+                        int(1, 4)
+                    ])
+                ])
             ]
         };
     }
@@ -50,33 +54,53 @@ mod test {
         parses_to! {
             parser: CalcParser,
             input: "-8829.5",
-            rule: Rule::float,
+            rule: Rule::expr,
             tokens: [
-                float(0, 7)
+                expr(0, 7, [
+                    neg(0, 7, [
+                        float(1, 7)
+                    ])
+                ])
             ]
         };
     }
 
     #[test]
-    fn rational() {
+    fn negative_rational() {
         parses_to! {
             parser: CalcParser,
-            input: "76/99",
-            rule: Rule::rational,
+            input: "-1/9",
+            rule: Rule::expr,
             tokens: [
-                rational(0, 5)
+                expr(0, 4, [
+                    neg(0, 2, [
+                        int(1, 2)
+                    ]),
+                    div(2, 3),
+                    int(3, 4),
+                ])
             ]
         };
     }
 
     #[test]
-    fn negative_rational() {
+    fn negative_power() {
         parses_to! {
             parser: CalcParser,
-            input: "-1/9",
-            rule: Rule::rational,
+            input: "-2^-2",
+            rule: Rule::expr,
             tokens: [
-                rational(0, 4)
+                expr(0, 5, [
+                    neg(0, 5, [
+                        power(1, 5, [
+                            int(1, 2),
+                            pow(2, 3),
+                            neg(3, 5, [
+                                int(4, 5)
+                            ])
+                        ])
+                    ])
+                ])
             ]
         };
     }
@@ -105,9 +129,271 @@ mod test {
             rule: Rule::expr,
             tokens: [
                 expr(0, 7, [
-                    int(1, 2),
-                    add(3, 4),
+                    expr(1, 6, [
+                        int(1, 2),
+                        add(3, 4),
+                        int(5, 6),
+                    ])
+                ])
+            ]
+        };
+    }
+
+    #[test]
+    fn imaginary() {
+        parses_to! {
+            parser: CalcParser,
+            input: "3 + 0.5i * i - in",
+            rule: Rule::expr,
+            tokens: [
+                expr(0, 17, [
+                    int(0, 1),
+                    add(2, 3),
+                    imaginary(4, 8),
+                    mul(9, 10),
+                    imaginary(11, 12),
+                    sub(13, 14),
+                    symbol(15, 17),
+                ])
+            ]
+        };
+    }
+
+    #[test]
+    fn quantity() {
+        parses_to! {
+            parser: CalcParser,
+            input: "9.8 m/s^2 * 2",
+            rule: Rule::expr,
+            tokens: [
+                expr(0, 13, [
+                    quantity(0, 9, [
+                        float(0, 3),
+                        unit(4, 9, [
+                            unit_power(4, 5, [
+                                unit_name(4, 5)
+                            ]),
+                            div(5, 6),
+                            unit_power(6, 9, [
+                                unit_name(6, 7),
+                                pow(7, 8),
+                                unit_exponent(8, 9)
+                            ])
+                        ])
+                    ]),
+                    mul(10, 11),
+                    int(12, 13)
+                ])
+            ]
+        };
+    }
+
+    #[test]
+    fn convert() {
+        parses_to! {
+            parser: CalcParser,
+            input: "5 km to mi",
+            rule: Rule::convert,
+            tokens: [
+                convert(0, 10, [
+                    expr(0, 5, [
+                        quantity(0, 5, [
+                            int(0, 1),
+                            unit(2, 5, [
+                                unit_power(2, 4, [
+                                    unit_name(2, 4)
+                                ])
+                            ])
+                        ])
+                    ]),
+                    to(5, 7),
+                    unit(8, 10, [
+                        unit_power(8, 10, [
+                            unit_name(8, 10)
+                        ])
+                    ])
+                ])
+            ]
+        };
+    }
+
+    #[test]
+    fn based_integers() {
+        parses_to! {
+            parser: CalcParser,
+            input: "0x1F + 0o17 * 0b10_11 - 1_000",
+            rule: Rule::expr,
+            tokens: [
+                expr(0, 29, [
+                    int(0, 4),
+                    add(5, 6),
+                    int(7, 11),
+                    mul(12, 13),
+                    int(14, 21),
+                    sub(22, 23),
+                    int(24, 29),
+                ])
+            ]
+        };
+    }
+
+    #[test]
+    fn bitwise() {
+        parses_to! {
+            parser: CalcParser,
+            input: "1 << 2 | ~3 xor 5",
+            rule: Rule::expr,
+            tokens: [
+                expr(0, 17, [
+                    int(0, 1),
+                    shl(2, 4),
                     int(5, 6),
+                    bit_or(7, 8),
+                    bit_not(9, 11, [
+                        int(10, 11)
+                    ]),
+                    bit_xor(12, 15),
+                    int(16, 17),
+                ])
+            ]
+        };
+    }
+
+    #[test]
+    fn comparisons() {
+        parses_to! {
+            parser: CalcParser,
+            input: "not x <= 2 and y != true",
+            rule: Rule::expr,
+            tokens: [
+                expr(0, 24, [
+                    logical_not(0, 10, [
+                        not(0, 3),
+                        relation(4, 10, [
+                            symbol(4, 5),
+                            le(6, 8),
+                            int(9, 10)
+                        ])
+                    ]),
+                    and(11, 14),
+                    symbol(15, 16),
+                    ne(17, 19),
+                    boolean(20, 24),
+                ])
+            ]
+        };
+    }
+
+    #[test]
+    fn conditional() {
+        parses_to! {
+            parser: CalcParser,
+            input: "if x then 1 else 2",
+            rule: Rule::conditional,
+            tokens: [
+                conditional(0, 18, [
+                    if_(0, 2),
+                    expr(3, 5, [
+                        symbol(3, 4)
+                    ]),
+                    then(5, 9),
+                    expr(10, 12, [
+                        int(10, 11)
+                    ]),
+                    else_(12, 16),
+                    expr(17, 18, [
+                        int(17, 18)
+                    ])
+                ])
+            ]
+        };
+    }
+
+    #[test]
+    fn program() {
+        parses_to! {
+            parser: CalcParser,
+            input: "a = 2; a*3;",
+            rule: Rule::program,
+            tokens: [
+                assn(0, 5, [
+                    symbol(0, 1),
+                    expr(4, 5, [
+                        int(4, 5)
+                    ])
+                ]),
+                expr(7, 10, [
+                    symbol(7, 8),
+                    mul(8, 9),
+                    int(9, 10)
+                ])
+            ]
+        };
+    }
+
+    #[test]
+    fn comments() {
+        parses_to! {
+            parser: CalcParser,
+            input: "a /* b */ + 1 # c",
+            rule: Rule::program,
+            tokens: [
+                expr(0, 13, [
+                    symbol(0, 1),
+                    add(10, 11),
+                    int(12, 13)
+                ])
+            ]
+        };
+    }
+
+    #[test]
+    fn lists() {
+        parses_to! {
+            parser: CalcParser,
+            input: "[1, x][0]",
+            rule: Rule::expr,
+            tokens: [
+                expr(0, 9, [
+                    index(0, 9, [
+                        list(0, 6, [
+                            expr(1, 2, [
+                                int(1, 2)
+                            ]),
+                            expr(4, 5, [
+                                symbol(4, 5)
+                            ])
+                        ]),
+                        expr(7, 8, [
+                            int(7, 8)
+                        ])
+                    ])
+                ])
+            ]
+        };
+    }
+
+    #[test]
+    fn equations() {
+        parses_to! {
+            parser: CalcParser,
+            input: "solve(x = 2, x)",
+            rule: Rule::expr,
+            tokens: [
+                expr(0, 15, [
+                    call(0, 15, [
+                        symbol(0, 5),
+                        expr(6, 8, [
+                            symbol(6, 7)
+                        ]),
+                        equals(8, 9),
+                        expr(10, 11, [
+                            int(10, 11)
+                        ]),
+                        expr(13, 14, [
+                            symbol(13, 14)
+                        ])
+                    ])
                 ])
             ]
         };
@@ -0,0 +1,261 @@
+//! Saving `State` between sessions.
+//!
+//! The front ends only store strings, so this module decides what those
+//! strings look like. Each one is an envelope recording the format version
+//! next to the state itself:
+//!
+//! ```json
+//! { "version": 1, "state": { "history": [], "assignments": {}, ... } }
+//! ```
+//!
+//! Anything saved before the envelope existed is a bare `State` and counts as
+//! version 0. Older versions are brought up to date by running them through
+//! `MIGRATIONS` one step at a time, and whatever still can't be read is
+//! dropped piece by piece, rather than throwing the whole state away.
+
+use pest::Parser;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Map, Value};
+use std::collections::HashMap;
+
+use model::{Function, Object, State};
+use parse::{CalcParser, Rule};
+
+/// The format version that `save` writes.
+pub const VERSION: u64 = 1;
+
+/// Upgrades a saved state from one version to the next. `MIGRATIONS[n]`
+/// takes the state part of a version `n` save to version `n + 1`, noting
+/// anything it had to give up on in the list of problems.
+type Migration = fn(Value, &mut Vec<String>) -> Value;
+
+const MIGRATIONS: &[Migration] = &[from_unversioned];
+
+/// Version 0 is the bare `State` from before saves were versioned. It has
+/// the same shape as version 1, except that it may be missing `functions`,
+/// which reading fills in anyway.
+fn from_unversioned(state: Value, _problems: &mut Vec<String>) -> Value {
+    state
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    version: u64,
+    state: &'a State,
+}
+
+/// The result of loading a saved state.
+pub struct Loaded {
+    /// As much of the saved state as could be read.
+    pub state: State,
+    /// Descriptions of everything that couldn't be carried over.
+    pub problems: Vec<String>,
+    /// The saved string exactly as it was, if anything was lost reading it,
+    /// so that the front end can keep a copy before it gets overwritten.
+    pub backup: Option<String>,
+}
+
+/// Turns `state` into a string for the front end to store.
+pub fn save(state: &State) -> String {
+    serde_json::to_string(&Envelope {
+        version: VERSION,
+        state,
+    }).unwrap()
+}
+
+/// Reads a string written by `save`, from this or any earlier version.
+pub fn load(saved: &str) -> Loaded {
+    let mut problems = Vec::new();
+    let state = match serde_json::from_str(saved) {
+        Ok(value) => migrate(value, &mut problems),
+        Err(error) => {
+            problems.push(format!("the saved state couldn't be read: {}", error));
+            State::new()
+        }
+    };
+
+    let backup = if problems.is_empty() {
+        None
+    } else {
+        Some(saved.to_string())
+    };
+    Loaded {
+        state,
+        problems,
+        backup,
+    }
+}
+
+fn migrate(value: Value, problems: &mut Vec<String>) -> State {
+    let (version, mut state) = match value {
+        Value::Object(mut envelope) if envelope.contains_key("version") => {
+            let version = envelope.get("version").and_then(Value::as_u64);
+            match (version, envelope.remove("state")) {
+                (Some(version), Some(state)) => (version, state),
+                _ => {
+                    problems.push("the saved state has a broken version header".to_string());
+                    return State::new();
+                }
+            }
+        }
+        state => (0, state),
+    };
+
+    if version > VERSION {
+        // There is nothing to migrate down with, but the newer format may
+        // still be close enough to read most of.
+        problems.push(format!(
+            "the saved state is from a newer version (format {}, this is format {})",
+            version, VERSION
+        ));
+    } else {
+        for migration in &MIGRATIONS[version as usize..] {
+            state = migration(state, problems);
+        }
+    }
+
+    read_state(state, problems)
+}
+
+/// Reads a state field by field, so that one variable or function that no
+/// longer makes sense doesn't take the rest down with it.
+fn read_state(value: Value, problems: &mut Vec<String>) -> State {
+    let mut fields = match value {
+        Value::Object(fields) => fields,
+        _ => {
+            problems.push("the saved state isn't an object".to_string());
+            return State::new();
+        }
+    };
+
+    let mut state = State::new();
+    if let Some(history) = fields.remove("history") {
+        match serde_json::from_value(history) {
+            Ok(history) => state.history = history,
+            Err(_) => problems.push("the history couldn't be read".to_string()),
+        }
+    }
+    if let Some(assignments) = fields.remove("assignments") {
+        state.assignments = read_map::<Object>("variable", assignments, problems);
+    }
+    if let Some(functions) = fields.remove("functions") {
+        let mut functions = read_map::<Function>("function", functions, problems);
+        // A body that doesn't parse any more would only fail once it is
+        // called, so it is dropped now like anything else unreadable.
+        functions.retain(|name, function| {
+            let parses = CalcParser::parse(Rule::body, &function.body).is_ok();
+            if !parses {
+                problems.push(format!("the function {} couldn't be read", name));
+            }
+            parses
+        });
+        state.functions = functions;
+    }
+
+    state
+}
+
+fn read_map<T: DeserializeOwned>(
+    kind: &str,
+    value: Value,
+    problems: &mut Vec<String>,
+) -> HashMap<String, T> {
+    let entries = match value {
+        Value::Object(entries) => entries,
+        _ => {
+            problems.push(format!("the saved {}s couldn't be read", kind));
+            Map::new()
+        }
+    };
+
+    entries
+        .into_iter()
+        .filter_map(|(name, entry)| match serde_json::from_value(entry) {
+            Ok(entry) => Some((name, entry)),
+            Err(_) => {
+                problems.push(format!("the {} {} couldn't be read", kind, name));
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use engine::evaluate;
+
+    #[test]
+    fn round_trip() {
+        let mut state = State::new();
+        evaluate(&mut state, "rate = 3/2");
+        evaluate(&mut state, "f(x) = x * rate");
+        evaluate(&mut state, "distance = 5 km");
+        state.add_entry("rate = 3/2");
+
+        let loaded = load(&save(&state));
+        assert!(loaded.problems.is_empty());
+        assert!(loaded.backup.is_none());
+
+        let mut state = loaded.state;
+        assert_eq!(state.history, vec!["rate = 3/2".to_string()]);
+        assert_eq!(evaluate(&mut state, "f(4)"), Object::Integer(6));
+        assert_eq!(evaluate(&mut state, "distance / m"), Object::Integer(5000));
+    }
+
+    #[test]
+    fn unversioned() {
+        // What the first release saved: a bare state with no functions.
+        let saved = r#"{"history":["x = 5"],"assignments":{"x":{"Integer":5},"pi":{"Float":3.14}},"counter":null}"#;
+
+        let loaded = load(saved);
+        assert!(loaded.problems.is_empty());
+        assert_eq!(loaded.state.assignments["x"], Object::Integer(5));
+        assert_eq!(loaded.state.history, vec!["x = 5".to_string()]);
+        assert!(loaded.state.functions.is_empty());
+    }
+
+    #[test]
+    fn partial_recovery() {
+        let saved = r#"{"version":1,"state":{"history":["x"],"assignments":{"x":{"Integer":5},"y":{"Quaternion":[1,2,3,4]}},"functions":{}}}"#;
+
+        let loaded = load(saved);
+        assert_eq!(loaded.problems, vec!["the variable y couldn't be read".to_string()]);
+        assert_eq!(loaded.backup, Some(saved.to_string()));
+        assert_eq!(loaded.state.assignments["x"], Object::Integer(5));
+        assert!(!loaded.state.assignments.contains_key("y"));
+    }
+
+    #[test]
+    fn unparsable_functions() {
+        let saved = r#"{"version":1,"state":{"history":[],"assignments":{},"functions":{"f":{"params":["x"],"body":"x + 1"},"g":{"params":["x"],"body":"[| x |]"},"h":{"params":["x"],"body":"x |> sqrt"}}}}"#;
+
+        let mut loaded = load(saved);
+        loaded.problems.sort();
+        assert_eq!(
+            loaded.problems,
+            vec![
+                "the function g couldn't be read".to_string(),
+                "the function h couldn't be read".to_string(),
+            ]
+        );
+        assert!(loaded.backup.is_some());
+        let mut state = loaded.state;
+        assert_eq!(evaluate(&mut state, "f(1)"), Object::Integer(2));
+        assert!(!state.functions.contains_key("g"));
+        assert!(!state.functions.contains_key("h"));
+    }
+
+    #[test]
+    fn unreadable() {
+        let loaded = load("{not json");
+        assert_eq!(loaded.problems.len(), 1);
+        assert_eq!(loaded.backup, Some("{not json".to_string()));
+        assert!(loaded.state.assignments.contains_key("pi"));
+
+        let loaded = load(r#"{"version":7,"state":{"history":["x"]}}"#);
+        assert_eq!(loaded.problems.len(), 1);
+        assert!(loaded.backup.is_some());
+        assert_eq!(loaded.state.history, vec!["x".to_string()]);
+    }
+}
@@ -0,0 +1,137 @@
+use num_rational::BigRational;
+use std::collections::HashMap;
+
+use model::{Dimension, Object, Quantity};
+
+/// Which prefixes a unit can take.
+#[derive(Clone, Copy)]
+enum Prefixes {
+    None,
+    /// Metric prefixes, like the `k` in `km`.
+    Si,
+    /// Metric prefixes and binary ones, like the `Gi` in `GiB`.
+    Data,
+}
+
+const SI_PREFIXES: &[(&str, i64)] = &[
+    ("P", 15),
+    ("T", 12),
+    ("G", 9),
+    ("M", 6),
+    ("k", 3),
+    ("h", 2),
+    ("d", -1),
+    ("c", -2),
+    ("m", -3),
+    ("µ", -6),
+    ("u", -6),
+    ("n", -9),
+    ("p", -12),
+];
+
+const BINARY_PREFIXES: &[(&str, i64)] = &[("Ki", 10), ("Mi", 20), ("Gi", 30), ("Ti", 40)];
+
+fn exact(numer: i64, denom: i64) -> Object {
+    BigRational::new(numer.into(), denom.into()).into()
+}
+
+/// Every unit that can be written after a number, keyed by name, including
+/// all of the prefixed forms like `km` and `MiB`.
+pub fn table() -> HashMap<String, Quantity> {
+    use model::Dimension as D;
+
+    let area = D::LENGTH * D::LENGTH;
+    let volume = area * D::LENGTH;
+    let frequency = D::default() / D::TIME;
+    let speed = D::LENGTH / D::TIME;
+    let force = D::MASS * speed / D::TIME;
+    let energy = force * D::LENGTH;
+    let power = energy / D::TIME;
+    let charge = D::CURRENT * D::TIME;
+    let voltage = power / D::CURRENT;
+    let resistance = voltage / D::CURRENT;
+    let flux = voltage * D::TIME;
+
+    // Exactly 1.602176634e-19 J, which is too small for an `i64` fraction.
+    let electronvolt = exact(1_602_176_634, 1) * Object::Integer(10).pow(Object::Integer(-28));
+
+    let units: Vec<(&str, Object, Dimension, Prefixes)> = vec![
+        // SI base units. The kilogram is the base unit of mass, but it is
+        // the gram that takes prefixes.
+        ("m", exact(1, 1), D::LENGTH, Prefixes::Si),
+        ("g", exact(1, 1000), D::MASS, Prefixes::Si),
+        ("s", exact(1, 1), D::TIME, Prefixes::Si),
+        ("A", exact(1, 1), D::CURRENT, Prefixes::Si),
+        ("K", exact(1, 1), D::TEMPERATURE, Prefixes::Si),
+        ("mol", exact(1, 1), D::AMOUNT, Prefixes::Si),
+        ("cd", exact(1, 1), D::LUMINOSITY, Prefixes::Si),
+        // SI derived units.
+        ("Hz", exact(1, 1), frequency, Prefixes::Si),
+        ("N", exact(1, 1), force, Prefixes::Si),
+        ("Pa", exact(1, 1), force / area, Prefixes::Si),
+        ("J", exact(1, 1), energy, Prefixes::Si),
+        ("W", exact(1, 1), power, Prefixes::Si),
+        ("C", exact(1, 1), charge, Prefixes::Si),
+        ("V", exact(1, 1), voltage, Prefixes::Si),
+        ("Ω", exact(1, 1), resistance, Prefixes::Si),
+        ("ohm", exact(1, 1), resistance, Prefixes::Si),
+        ("F", exact(1, 1), charge / voltage, Prefixes::Si),
+        ("S", exact(1, 1), D::default() / resistance, Prefixes::Si),
+        ("Wb", exact(1, 1), flux, Prefixes::Si),
+        ("T", exact(1, 1), flux / area, Prefixes::Si),
+        ("H", exact(1, 1), flux / D::CURRENT, Prefixes::Si),
+        ("L", exact(1, 1000), volume, Prefixes::Si),
+        ("Wh", exact(3600, 1), energy, Prefixes::Si),
+        ("eV", electronvolt, energy, Prefixes::Si),
+        ("atm", exact(101_325, 1), force / area, Prefixes::None),
+        // Time.
+        ("min", exact(60, 1), D::TIME, Prefixes::None),
+        ("h", exact(3600, 1), D::TIME, Prefixes::None),
+        ("day", exact(86_400, 1), D::TIME, Prefixes::None),
+        ("week", exact(604_800, 1), D::TIME, Prefixes::None),
+        ("yr", exact(31_557_600, 1), D::TIME, Prefixes::None),
+        // Imperial and US customary units.
+        ("in", exact(254, 10_000), D::LENGTH, Prefixes::None),
+        ("ft", exact(3048, 10_000), D::LENGTH, Prefixes::None),
+        ("yd", exact(9144, 10_000), D::LENGTH, Prefixes::None),
+        ("mi", exact(1_609_344, 1000), D::LENGTH, Prefixes::None),
+        ("mph", exact(1_609_344, 3_600_000), speed, Prefixes::None),
+        ("lb", exact(45_359_237, 100_000_000), D::MASS, Prefixes::None),
+        ("oz", exact(45_359_237, 1_600_000_000), D::MASS, Prefixes::None),
+        ("gal", exact(3_785_411_784, 1_000_000_000_000), volume, Prefixes::None),
+        // Data sizes.
+        ("bit", exact(1, 1), D::INFORMATION, Prefixes::Data),
+        ("B", exact(8, 1), D::INFORMATION, Prefixes::Data),
+        ("byte", exact(8, 1), D::INFORMATION, Prefixes::None),
+    ];
+
+    let mut table = HashMap::new();
+    for &(name, ref scale, dimension, _) in &units {
+        table.insert(name.to_string(), Quantity::unit(name, scale.clone(), dimension));
+    }
+
+    // Unprefixed names win, so `min` stays a minute rather than a
+    // milli-inch.
+    for (name, scale, dimension, prefixes) in units {
+        let binary = match prefixes {
+            Prefixes::None => continue,
+            Prefixes::Si => &[][..],
+            Prefixes::Data => BINARY_PREFIXES,
+        };
+
+        let decimal = SI_PREFIXES
+            .iter()
+            .map(|&(prefix, power)| (prefix, Object::Integer(10).pow(Object::Integer(power))));
+        let binary = binary
+            .iter()
+            .map(|&(prefix, power)| (prefix, Object::Integer(2).pow(Object::Integer(power))));
+
+        for (prefix, factor) in decimal.chain(binary) {
+            let prefixed = format!("{}{}", prefix, name);
+            let unit = Quantity::unit(&prefixed, factor * scale.clone(), dimension);
+            table.entry(prefixed).or_insert(unit);
+        }
+    }
+
+    table
+}